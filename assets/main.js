@@ -24,14 +24,21 @@ const SortMode = {
 };
 let currentSortMode = localStorage.getItem("sortMode") || SortMode.DATE;
 
+// Whether the preview shows the stored article content rather than just the summary
+let readerView = localStorage.getItem("readerView") === "true";
+
+// Whether the preview shows the feed's own title and summary rather than the summarised ones
+let originalView = localStorage.getItem("originalView") === "true";
+
 const createArticleElement = (article) => {
     const articleElement = document.createElement("div");
     articleElement.classList.add("article");
     articleElement.innerHTML = `
-        <a class="article-link" href="${article.link}">${article.title}</a>
+        <a class="article-link" href="${article.url || article.link}">${article.title}</a>
         <div class="article-details">
-            <img class="article-icon" src="${article.channel.icon}">
+            <img class="article-icon" src="${article.channel.icon_thumbnail || article.channel.icon}">
             <div class="article-date">${format_time_ago(article.published)}</div>
+            ${article.updated ? '<div class="article-updated">Updated</div>' : ""}
         </div>
     `;
 
@@ -76,8 +83,17 @@ const createArticleElement = (article) => {
 
 const fetchArticles = async () => {
     try {
-        const response = await fetch("/articles");
-        const data = await response.json();
+        // Page through all the articles
+        const data = [];
+        let cursor = null;
+        do {
+            const params = new URLSearchParams({ limit: 500 });
+            if (cursor) params.set("cursor", cursor);
+            const response = await fetch(`/articles?${params}`);
+            const page = await response.json();
+            data.push(...page.articles);
+            cursor = page.next_cursor;
+        } while (cursor);
 
         // Convert and sort articles
         const articles = data.map((article) => {
@@ -129,21 +145,105 @@ const highlightCurrentArticle = () => {
         selectedArticle.scrollIntoView({ behavior: "smooth", block: "center" });
 
         // Setup preview
-        document.getElementById("preview-header").innerHTML = selectedArticle.data.title;
-        document.getElementById("preview-date").innerHTML = selectedArticle.data.published.toDateString();
-        document.getElementById("preview-text").innerHTML = selectedArticle.data.summary;
-        document.getElementById("preview-image").src = selectedArticle.data.image;
+        const article = selectedArticle.data;
+        document.getElementById("preview-header").innerHTML =
+            (originalView && article.original_title) || article.title;
+        const updated = article.updated ? [`Updated ${new Date(article.updated).toDateString()}`] : [];
+        document.getElementById("preview-date").textContent = [article.published.toDateString(), ...updated, ...(article.authors || [])].join(" · ");
+        document.getElementById("preview-text").innerHTML =
+            (originalView && article.original_summary) || article.summary;
+        showSummaryDetails(originalView ? { key_points: [], tags: article.categories } : article);
+        showSources(article);
+        document.getElementById("preview-image").src = selectedArticle.data.thumbnail || selectedArticle.data.image;
+        showArticleContent(selectedArticle.data);
     } else {
         // Clean preview
         document.getElementById("preview-header").innerHTML = "";
         document.getElementById("preview-date").innerHTML = "";
         document.getElementById("preview-text").innerHTML = "";
+        showSummaryDetails({ key_points: [], tags: [] });
+        showSources({ sources: [] });
         document.getElementById("preview-image").src = "";
+        document.getElementById("preview-content").innerHTML = "";
     }
     columns[currentColumn].classList.add("selected");
     columns[currentColumn].parentElement.classList.add("selected");
 };
 
+// Show the key points and tags a summary prompt asked for, if any
+const showSummaryDetails = (article) => {
+    const points = document.getElementById("preview-points");
+    points.replaceChildren(
+        ...(article.key_points || []).map((point) => {
+            const item = document.createElement("li");
+            item.textContent = point;
+            return item;
+        })
+    );
+    document.getElementById("preview-tags").textContent = (article.tags || []).map((tag) => `#${tag}`).join(" ");
+};
+
+// Link the other feeds that carried the same story
+const showSources = (article) => {
+    const links = (article.sources || []).map((source) => {
+        const link = document.createElement("a");
+        link.href = source.link;
+        link.title = source.title;
+        link.textContent = new URL(source.link).hostname;
+        return link;
+    });
+    const sources = document.getElementById("preview-sources");
+    sources.replaceChildren(...(links.length > 0 ? ["Also from ", ...links] : []));
+};
+
+const showArticleContent = async (article) => {
+    const previewContent = document.getElementById("preview-content");
+    if (!readerView) {
+        previewContent.innerHTML = "";
+        return;
+    }
+
+    // Fetch the stored content once per article, from the feed in the original view
+    const source = originalView ? "feed" : "page";
+    article.content = article.content || {};
+    if (article.content[source] === undefined) {
+        try {
+            const response = await fetch(`/articles/${encodeURIComponent(article.link)}/content?source=${source}`);
+            const isHtml = (response.headers.get("content-type") || "").startsWith("text/html");
+            article.content[source] = isHtml ? await response.text() : null;
+        } catch (error) {
+            console.error("Error fetching article content:", error);
+            return;
+        }
+    }
+
+    // Only show it if the article is still selected
+    if (currentArticle[currentColumn] === article.link) {
+        previewContent.innerHTML = article.content[source] || "<p>No stored content for this article.</p>";
+    }
+};
+
+// Summarise an article again, keeping its previous summary on the server
+const resummarizeArticle = async (articleElement) => {
+    const article = articleElement.data;
+    try {
+        const response = await fetch(`/articles/${encodeURIComponent(article.link)}/resummarize`, {
+            method: "POST",
+        });
+        const result = await response.json();
+        if (result.status !== "success") {
+            console.error("Error re-summarising article:", result.message);
+            return;
+        }
+        Object.assign(article, result.article, { published: new Date(result.article.published) });
+        delete article.content;
+        articleElement.querySelector(".article-link").innerHTML = article.title;
+        highlightCurrentArticle();
+    } catch (error) {
+        console.error("Error re-summarising article:", error);
+    }
+};
+
 const undoStack = [];
 const redoStack = [];
 const columnsMap = {
@@ -240,8 +340,9 @@ document.addEventListener("keydown", async (event) => {
             }
             break;
         case "Enter":
-            if (currentArticle[currentColumn]) {
-                window.open(currentArticle[currentColumn]);
+            if (currentIndex !== -1) {
+                const { url, link } = articles[currentIndex].data;
+                window.open(url || link);
             }
             break;
         case "z":
@@ -279,6 +380,22 @@ document.addEventListener("keydown", async (event) => {
             currentSortMode = currentSortMode === SortMode.DATE ? SortMode.SOURCE : SortMode.DATE;
             localStorage.setItem("sortMode", currentSortMode);
             sortColumnsByCurrentMode();
+            break;
+        case "v":
+            readerView = !readerView;
+            localStorage.setItem("readerView", readerView);
+            highlightCurrentArticle();
+            break;
+        case "o":
+            originalView = !originalView;
+            localStorage.setItem("originalView", originalView);
+            highlightCurrentArticle();
+            break;
+        case "R":
+            if (currentIndex !== -1) {
+                resummarizeArticle(articles[currentIndex]);
+            }
+            break;
     }
 });
 
@@ -1,8 +1,12 @@
-use axum::{
-    extract::Path,
-    response::{IntoResponse, Json},
+use crate::{
+    content::ContentSource,
+    error::Error,
+    extract::QueryParams,
+    summarizer::{ExtractiveSummarizer, Summarizer},
 };
-use feed_rs::parser;
+use axum::{extract::Path, response::Json};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use futures::{stream, StreamExt};
 use piped::PipedClient;
 use readability::extractor;
@@ -10,11 +14,20 @@ use reqwest::Client;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sled::Db;
-use std::{io::Cursor, str::FromStr, sync::Arc};
+use sha2::{Digest, Sha256};
+use sled::{Batch, Db};
+use std::{
+    collections::HashSet,
+    io::Cursor,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
-enum ReadStatus {
+pub enum ReadStatus {
     Fresh,
     Saved,
     Archived,
@@ -32,67 +45,165 @@ impl FromStr for ReadStatus {
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
-struct Article {
-    link: String,
-    channel: String,
-    title: String,
-    published: String,
-    image: String,
-    summary: String,
-    read_status: ReadStatus,
+pub struct Article {
+    pub link: String,
+    pub channel: String,
+    pub title: String,
+    pub published: String,
+    pub image: String,
+    pub summary: String,
+    pub read_status: ReadStatus,
+    #[serde(default)]
+    pub summary_key: Option<String>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub summary_error: Option<String>,
+    #[serde(default)]
+    pub key_points: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub clickbait_score: Option<f32>,
+    #[serde(default)]
+    pub original_title: Option<String>,
+    #[serde(default)]
+    pub original_summary: Option<String>,
+    #[serde(default)]
+    pub previous_summaries: Vec<SummaryVersion>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// SimHash of the content, to spot the same story elsewhere.
+    #[serde(default)]
+    pub fingerprint: Option<u64>,
+    /// Other links that lead to this article.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Other feed entries with the same story.
+    #[serde(default)]
+    pub sources: Vec<ArticleSource>,
+    /// Id of the feed entry it came from.
+    #[serde(default)]
+    pub guid: Option<String>,
+    /// When it was last refreshed because its feed entry changed.
+    #[serde(default)]
+    pub updated: Option<String>,
+    /// The link as its feed gave it, which is opened and scraped. `link` is only its canonical
+    /// form, to find it by.
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+impl Article {
+    /// Where the article is read, its link as the feed gave it if that was kept.
+    pub fn url(&self) -> &str {
+        self.url.as_deref().unwrap_or(&self.link)
+    }
+}
+
+/// A feed entry found to be the same story as an article already stored.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct ArticleSource {
+    pub channel: String,
+    pub link: String,
+    pub title: String,
+    #[serde(default)]
+    pub guid: Option<String>,
+}
+
+/// A summary of an article that was replaced by re-summarising it.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct SummaryVersion {
+    pub title: String,
+    pub summary: String,
+    pub summary_key: Option<String>,
+    pub key_points: Vec<String>,
+    pub tags: Vec<String>,
+    pub clickbait_score: Option<f32>,
+    /// When it was replaced.
+    pub replaced: String,
 }
 
 #[derive(Debug)]
 struct WebpageData {
     image: Option<String>,
-    summary: Option<String>,
-    new_title: Option<String>,
+    main_content: Option<String>,
+    content: Option<String>,
+    canonical: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug)]
+struct SummaryData {
+    summary: Option<Summary>,
+    summary_key: Option<String>,
+    summary_error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
 pub struct Summary {
+    #[serde(alias = "Title")]
     pub title: String,
+    #[serde(alias = "Summary")]
     pub summary: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_points: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clickbait_score: Option<f32>,
 }
 
 /// Website scraping for data
-async fn scrape_website(
-    db: Arc<Db>,
-    title: String,
-    url: &str,
-) -> Result<WebpageData, Box<dyn std::error::Error>> {
+async fn scrape_website(url: &str) -> Result<WebpageData, Error> {
     // Download the webpage and parse the html
-    let resp = reqwest::get(url).await?;
-    let body = resp.text().await?;
-    let document = Html::parse_document(&body);
-
-    // Get the first image
-    let mut image = Selector::parse("img").ok().and_then(|selector| {
-        document
-            .select(&selector)
-            .next()
-            .and_then(|element| element.value().attr("src"))
-            .map(String::from)
-    });
+    let scrape_error = |e: reqwest::Error| Error::Scrape(format!("{url}: {e}"));
+    let resp = reqwest::get(url).await.map_err(scrape_error)?;
+    let body = resp.text().await.map_err(scrape_error)?;
+
+    // Get the first image and the canonical link
+    let (mut image, canonical) = {
+        let document = Html::parse_document(&body);
+        let first = |selector: &str, attr: &str| {
+            Selector::parse(selector).ok().and_then(|selector| {
+                document
+                    .select(&selector)
+                    .next()
+                    .and_then(|element| element.value().attr(attr))
+                    .map(String::from)
+            })
+        };
+        let canonical = first("link[rel=canonical]", "href").and_then(|canonical| {
+            // Canonical links can be relative to the page
+            let canonical = url::Url::parse(url).ok()?.join(&canonical).ok()?;
+            Some(crate::dedupe::canonicalize(canonical.as_str()))
+        });
+        (first("img", "src"), canonical)
+    };
 
     // Get the main content using the readability crate
     let mut main_content = match url::Url::parse(url) {
         Ok(url_obj) => {
             let mut body_cursor = Cursor::new(body);
-            let main_content = extractor::extract(&mut body_cursor, &url_obj)
+            extractor::extract(&mut body_cursor, &url_obj)
                 .ok()
-                .map(|content| content.content);
-            Some(main_content.unwrap())
+                .map(|content| content.content)
         }
         Err(_) => None,
     };
 
+    // Keep the readable content for offline reading
+    let mut content = main_content.clone();
+
     // Youtube specific
-    let split_result = url.split('=').collect::<Vec<_>>();
-    let is_youtube = url.contains("youtube.com") && split_result.len() > 1;
-    if is_youtube {
+    let video_id = url
+        .rsplit_once('=')
+        .filter(|_| url.contains("youtube.com"))
+        .map(|(_, id)| id);
+    if let Some(video_id) = video_id {
         let client = PipedClient::new(&Client::new(), "https://pipedapi.kavin.rocks");
-        if let Ok(video) = client.video_from_id(split_result.last().unwrap()).await {
+        if let Ok(video) = client.video_from_id(video_id).await {
             // Set image to the videos thumbnail
             image = Some(video.thumbnail_url);
 
@@ -110,8 +221,8 @@ async fn scrape_website(
                 .map(|subtitle| subtitle.url.clone());
             // If best subtitle exists, download the xml from url and parse out the text
             if let Some(subtitle_url) = best_subtitles {
-                let resp = reqwest::get(subtitle_url).await?;
-                let body = resp.text().await?;
+                let resp = reqwest::get(subtitle_url).await.map_err(scrape_error)?;
+                let body = resp.text().await.map_err(scrape_error)?;
 
                 // Parse out the subtitle text
                 let mut text = String::new();
@@ -120,7 +231,10 @@ async fn scrape_website(
                     let start_pos = start + start_tag;
                     if let Some(end_tag) = body[start_pos..].find("</p>") {
                         let end_pos = start_pos + end_tag;
-                        let content_start = body[start_pos..].find('>').unwrap() + 1 + start_pos;
+                        let Some(content_start) = body[start_pos..end_pos].find('>') else {
+                            break;
+                        };
+                        let content_start = content_start + 1 + start_pos;
                         let raw_text = &body[content_start..end_pos];
 
                         // Decode HTML entities
@@ -134,106 +248,582 @@ async fn scrape_website(
                     }
                 }
 
+                content = Some(crate::content::text_to_html(&text));
                 main_content = Some(text);
             }
         }
     }
 
-    // Use GPT3.5 to summarize the article
-    let summary = if main_content.is_some() {
-        let summary = crate::gpt::summarise_article(db, title, main_content.unwrap()).await;
-        if summary.is_err() {
-            println!("Error summarising article: {:?}", summary.err().unwrap());
-            None
-        } else {
-            Some(summary.unwrap())
+    Ok(WebpageData {
+        image,
+        main_content,
+        content,
+        canonical,
+    })
+}
+
+/// Summarize the main content of a page with the configured summariser, falling back to picking
+/// out its key sentences so the article is never dropped for want of a summary
+async fn summarise_page(
+    db: Arc<Db>,
+    summarizer: &dyn Summarizer,
+    prompt: &str,
+    channel: &str,
+    title: String,
+    main_content: Option<String>,
+    refresh: bool,
+) -> SummaryData {
+    let (summary, summary_key, summary_error) = match main_content {
+        Some(main_content) => {
+            let summary_key = summarizer
+                .cacheable()
+                .then(|| crate::gpt::summary_key(summarizer, prompt, &title, &main_content));
+            match crate::gpt::summarise_article(
+                db,
+                summarizer,
+                channel,
+                prompt,
+                title.clone(),
+                main_content.clone(),
+                refresh,
+            )
+            .await
+            {
+                Ok(summary) => (Some(summary), summary_key, None),
+                Err(e) => {
+                    println!("Error summarising article, using an extractive summary: {e:?}");
+                    let summary =
+                        ExtractiveSummarizer::default().summarise_text(&title, &main_content);
+                    (summary, None, Some(e.to_string()))
+                }
+            }
         }
-    } else {
-        None
+        None => (None, None, Some("No readable content found".to_string())),
     };
 
-    if let Some(summary) = summary {
-        return Ok(WebpageData {
-            image,
-            summary: Some(summary.summary),
-            new_title: Some(summary.title),
-        });
+    SummaryData {
+        summary,
+        summary_key,
+        summary_error,
+    }
+}
+
+/// Keep a local thumbnail of an article's image, returning the path it is served from.
+async fn cache_thumbnail(db: &Db, link: &str, image: &str) -> Option<String> {
+    if image.is_empty() {
+        return None;
+    }
+    // Images scraped from the page can be relative to it
+    let image = url::Url::parse(link)
+        .and_then(|link| link.join(image))
+        .map_or_else(|_| image.to_string(), String::from);
+    match crate::media::cache_image(db, &image, crate::media::THUMBNAIL_SIZE).await {
+        Ok(path) => Some(path),
+        Err(e) => {
+            eprintln!("Error caching image {image}: {e}");
+            None
+        }
+    }
+}
+
+/// Pull the new and changed entries of a channel into the database, recording how it went in
+/// the channel's health.
+pub async fn process_source(source: &String, db: Arc<Db>) -> Result<(), Error> {
+    let mut pull = crate::health::Pull::start();
+    let result = pull_source(source, &db, &mut pull).await;
+    if let Err(e) = crate::health::record_pull(&db, source, &pull, &result) {
+        eprintln!("Error recording health of {source}: {e}");
     }
-    Err(Box::new(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        "Failed to scrape website",
-    )))
+    result
 }
 
-pub async fn process_source(
+async fn pull_source(
     source: &String,
-    db: Arc<Db>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    db: &Arc<Db>,
+    pull: &mut crate::health::Pull,
+) -> Result<(), Error> {
     println!("Processing source {source}");
-    let response = reqwest::get(source).await?;
-    let bytes = response.bytes().await?;
-    let cursor = Cursor::new(bytes);
-    let feed = parser::parse(cursor)?;
+    let scrape = crate::config::load()?
+        .rss
+        .into_iter()
+        .find(|channel| channel.rss_url == *source)
+        .and_then(|channel| channel.scrape);
+    let Some((feed, feed_state)) =
+        crate::feed::fetch_if_changed(db, source, scrape.as_ref(), pull).await?
+    else {
+        println!("Source {source} is unchanged or not due");
+        return Ok(());
+    };
+    pull.entries_seen = feed.entries.len();
 
+    let channel_title = crate::channel::get_channel_from_db(db, source)
+        .map(|channel| channel.title)
+        .unwrap_or_default();
+    let (summarizer, prompt) = crate::summarizer::load(source);
+    let new = AtomicUsize::new(0);
     stream::iter(feed.entries.iter())
         .for_each_concurrent(4, |entry| {
             let db = db.clone();
-            let source = source.clone();
+            let channel_title = &channel_title;
+            let summarizer = summarizer.as_ref();
+            let prompt = &prompt;
+            let new = &new;
             async move {
-                let entry_title = entry.title.clone().map_or(String::new(), |t| t.content);
-                let entry_summary = entry.summary.clone().map_or(String::new(), |s| s.content);
-                let entry_published = entry.published.unwrap_or_default();
-                let entry_link = entry
-                    .links
-                    .first()
-                    .map_or(entry.id.clone(), |link| link.href.clone());
-
-                // Get first image in content if exists
-                let entry_image = entry
-                    .content
-                    .clone()
-                    .and_then(|content| content.body)
-                    .and_then(|body| {
-                        let document = Html::parse_document(&body);
-                        let image = Selector::parse("img").ok().and_then(|selector| {
-                            document
-                                .select(&selector)
-                                .next()
-                                .and_then(|element| element.value().attr("src"))
-                                .map(String::from)
-                        });
-                        image
-                    });
-
-                // Check if the article is already in the database
-                if let Ok(false) = db.contains_key(format!("article:{}", &entry_link)) {
-                    // Download the webpage and extract the image
-                    if let Ok(data) =
-                        scrape_website(db.clone(), entry_title.clone(), &entry_link).await
-                    {
-                        let article = Article {
-                            link: entry_link,
-                            channel: source.clone(),
-                            title: data.new_title.unwrap_or(entry_title),
-                            published: entry_published.to_rfc3339(),
-                            image: entry_image.unwrap_or(data.image.unwrap_or_default()),
-                            summary: data.summary.unwrap_or(entry_summary),
-                            read_status: ReadStatus::Fresh,
-                        };
-                        if let Err(e) = store_article_to_db(&db, &article) {
-                            eprintln!("Error storing article to database: {e}");
-                        }
-                    };
+                let entry = EntryData::new(entry);
+                match process_entry(db, summarizer, prompt, source, channel_title, entry).await {
+                    Ok(true) => {
+                        new.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Error processing entry of {source}: {e}"),
                 }
             }
         })
         .await;
+    pull.entries_new = new.into_inner();
+
+    // Forget the entries that left the feed, only those still in it can be imported again
+    let guids: HashSet<&str> = feed.entries.iter().map(|entry| entry.id.as_str()).collect();
+    delete_departed_entry_states(db, source, &guids)?;
+
+    // Remember the feed even if some entries failed. Those keep no state of their new version, so
+    // they are retried once the feed changes and is downloaded again
+    crate::feed::store_feed_state_to_db(db, source, &feed_state)
+}
+
+/// The fields kept from a feed entry.
+struct EntryData {
+    guid: String,
+    title: String,
+    summary: String,
+    /// The link as the feed gave it, pages are downloaded from it.
+    raw_link: String,
+    /// The canonical form of the link, articles are stored under it.
+    link: String,
+    published: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+    authors: Vec<String>,
+    categories: Vec<String>,
+    content: Option<String>,
+    image: Option<String>,
+}
+
+impl EntryData {
+    fn new(entry: &feed_rs::model::Entry) -> Self {
+        let raw_link = entry
+            .links
+            .first()
+            .map_or(entry.id.clone(), |link| link.href.clone());
+        let content = entry.content.clone().and_then(|content| content.body);
+
+        // Get first image in content if exists
+        let image = content.as_deref().and_then(|body| {
+            let document = Html::parse_document(body);
+            let image = Selector::parse("img").ok().and_then(|selector| {
+                document
+                    .select(&selector)
+                    .next()
+                    .and_then(|element| element.value().attr("src"))
+                    .map(String::from)
+            });
+            image
+        });
+
+        Self {
+            guid: entry.id.clone(),
+            title: entry.title.clone().map_or(String::new(), |t| t.content),
+            summary: entry.summary.clone().map_or(String::new(), |s| s.content),
+            link: crate::dedupe::canonicalize(&raw_link),
+            raw_link,
+            published: entry.published.unwrap_or_default(),
+            updated: entry.updated,
+            authors: entry
+                .authors
+                .iter()
+                .map(|author| author.name.clone())
+                .collect(),
+            categories: entry
+                .categories
+                .iter()
+                .map(|category| category.label.clone().unwrap_or(category.term.clone()))
+                .collect(),
+            content,
+            image,
+        }
+    }
+
+    /// Hash of what the feed says about the entry, to notice when it is edited.
+    fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            &self.title,
+            &self.summary,
+            self.content.as_deref().unwrap_or_default(),
+        ] {
+            hasher.update((field.len() as u64).to_le_bytes());
+            hasher.update(field.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Fill in the fields an article keeps from its feed entry.
+    fn apply_original(&self, article: &mut Article) {
+        article.original_title = Some(self.title.clone());
+        article.original_summary = Some(crate::content::sanitize(&self.summary));
+        article.authors.clone_from(&self.authors);
+        article.categories.clone_from(&self.categories);
+    }
+}
+
+/// What was last seen of a feed entry, stored under its channel and id.
+#[derive(Deserialize, Serialize, Debug)]
+struct EntryState {
+    /// Link of the article it is part of.
+    link: String,
+    updated: Option<String>,
+    hash: String,
+}
+
+fn entry_state_key(channel: &str, guid: &str) -> String {
+    format!("entry:{channel}\0{guid}")
+}
+
+/// Function to retrieve what was last seen of a feed entry from the database.
+fn get_entry_state_from_db(db: &Db, channel: &str, guid: &str) -> Option<EntryState> {
+    let ivec = db.get(entry_state_key(channel, guid)).ok()??;
+    serde_json::from_slice(&ivec).ok()
+}
 
+/// Function to store what was seen of a feed entry into the database.
+fn store_entry_state_to_db(
+    db: &Db,
+    channel: &str,
+    entry: &EntryData,
+    link: &str,
+) -> Result<(), Error> {
+    let state = EntryState {
+        link: link.to_string(),
+        updated: entry.updated.map(|updated| updated.to_rfc3339()),
+        hash: entry.hash(),
+    };
+    db.insert(
+        entry_state_key(channel, &entry.guid),
+        serde_json::to_vec(&state)?,
+    )?;
+    db.flush()?;
     Ok(())
 }
 
+/// Function to remove the states of a channel's entries that are no longer in its feed.
+fn delete_departed_entry_states(
+    db: &Db,
+    channel: &str,
+    guids: &HashSet<&str>,
+) -> Result<(), Error> {
+    let prefix = entry_state_key(channel, "");
+    for key in db.scan_prefix(&prefix).keys() {
+        let key = key?;
+        if !guids.contains(String::from_utf8_lossy(&key[prefix.len()..]).as_ref()) {
+            db.remove(key)?;
+        }
+    }
+    db.flush()?;
+    Ok(())
+}
+
+/// Store a new feed entry as an article, or bring the article it is part of up to date.
+/// Returns whether the entry became a new article.
+async fn process_entry(
+    db: Arc<Db>,
+    summarizer: &dyn Summarizer,
+    prompt: &str,
+    source: &str,
+    channel_title: &str,
+    entry: EntryData,
+) -> Result<bool, Error> {
+    // Entries are known by their id within the channel, as their links can change
+    if let Some(state) = get_entry_state_from_db(&db, source, &entry.guid) {
+        // Its article was removed by retention, and stays removed while the entry is in the feed
+        if !db.contains_key(format!("article:{}", state.link))? {
+            return Ok(false);
+        }
+        return update_entry(db, summarizer, prompt, source, channel_title, entry, state)
+            .await
+            .map(|()| false);
+    }
+
+    // Articles stored before entries were tracked are found by their link
+    if let Some(link) = find_article_link(&db, &[&entry.link, &entry.raw_link]) {
+        let mut article = get_article_from_db(&db, &link)?;
+        if article.original_title.is_none() && article.channel == source {
+            entry.apply_original(&mut article);
+            store_article_to_db(&db, &article)?;
+            store_feed_content(&db, &article.link, entry.content.as_deref());
+        }
+        store_entry_state_to_db(&db, source, &entry, &link)?;
+        return Ok(false);
+    }
+
+    // Download the webpage and extract the image, from the link as the feed gave it
+    let data = scrape_website(&entry.raw_link).await?;
+
+    // The same story from elsewhere becomes another source of the stored article
+    let link = data.canonical.clone().unwrap_or(entry.link.clone());
+    let text = data
+        .main_content
+        .as_deref()
+        .map(crate::search::html_to_text);
+    let fingerprint = text.as_deref().and_then(crate::dedupe::fingerprint);
+    let existing = find_article_link(&db, &[&link])
+        .or_else(|| find_duplicate(&db, source, &entry.title, fingerprint, &entry.published));
+    if let Some(existing) = existing {
+        let entry_source = ArticleSource {
+            channel: source.to_string(),
+            link: entry.raw_link.clone(),
+            title: entry.title.clone(),
+            guid: Some(entry.guid.clone()),
+        };
+        add_source_to_db(&db, &existing, entry_source)?;
+        store_entry_state_to_db(&db, source, &entry, &existing)?;
+        return Ok(false);
+    }
+
+    let data_summary = summarise_page(
+        db.clone(),
+        summarizer,
+        prompt,
+        source,
+        entry.title.clone(),
+        data.main_content,
+        false,
+    )
+    .await;
+    let image = entry
+        .image
+        .clone()
+        .unwrap_or(data.image.unwrap_or_default());
+    let thumbnail = cache_thumbnail(&db, &link, &image).await;
+    let summary = data_summary.summary.unwrap_or(Summary {
+        title: entry.title.clone(),
+        summary: entry.summary.clone(),
+        ..Summary::default()
+    });
+    let aliases: Vec<String> = (link != entry.link)
+        .then(|| entry.link.clone())
+        .into_iter()
+        .collect();
+    let mut article = Article {
+        link,
+        channel: source.to_string(),
+        title: summary.title,
+        published: entry.published.to_rfc3339(),
+        image,
+        thumbnail,
+        summary: summary.summary,
+        read_status: ReadStatus::Fresh,
+        summary_key: data_summary.summary_key,
+        summary_error: data_summary.summary_error,
+        key_points: summary.key_points,
+        tags: summary.tags,
+        clickbait_score: summary.clickbait_score,
+        original_title: None,
+        original_summary: None,
+        previous_summaries: Vec::new(),
+        authors: Vec::new(),
+        categories: Vec::new(),
+        fingerprint,
+        aliases,
+        sources: Vec::new(),
+        guid: Some(entry.guid.clone()),
+        updated: None,
+        url: Some(entry.raw_link.clone()),
+    };
+    entry.apply_original(&mut article);
+    store_article_to_db(&db, &article)?;
+    for alias in &article.aliases {
+        if let Err(e) = db.insert(format!("alias:{alias}"), article.link.as_bytes()) {
+            eprintln!("Error storing article alias: {e}");
+        }
+    }
+    store_page(
+        &db,
+        &article,
+        channel_title,
+        data.content.as_deref(),
+        text.as_deref(),
+    );
+    store_feed_content(&db, &article.link, entry.content.as_deref());
+    store_entry_state_to_db(&db, source, &entry, &article.link)?;
+    Ok(true)
+}
+
+/// Bring the article a known feed entry is part of up to date, if the entry changed. Its read
+/// status is kept, it is only marked as updated.
+async fn update_entry(
+    db: Arc<Db>,
+    summarizer: &dyn Summarizer,
+    prompt: &str,
+    source: &str,
+    channel_title: &str,
+    entry: EntryData,
+    state: EntryState,
+) -> Result<(), Error> {
+    let mut article = get_article_from_db(&db, &state.link)?;
+
+    // A new link of the entry leads to the same article
+    if entry.link != article.link && !article.aliases.contains(&entry.link) {
+        db.insert(format!("alias:{}", entry.link), article.link.as_bytes())?;
+        article.aliases.push(entry.link.clone());
+        store_article_to_db(&db, &article)?;
+    }
+
+    // Only the channel the article came from updates it, not those it was found in later
+    let updated = entry.updated.map(|updated| updated.to_rfc3339());
+    let changed = entry.hash() != state.hash || updated > state.updated;
+    if !changed || article.channel != source {
+        return store_entry_state_to_db(&db, source, &entry, &article.link);
+    }
+
+    println!("Entry {} of {source} was updated", entry.guid);
+    let data = scrape_website(&entry.raw_link).await?;
+    let text = data
+        .main_content
+        .as_deref()
+        .map(crate::search::html_to_text);
+    let data_summary = summarise_page(
+        db.clone(),
+        summarizer,
+        prompt,
+        source,
+        entry.title.clone(),
+        data.main_content,
+        false,
+    )
+    .await;
+
+    // Read it again, as its status may have changed while the page was summarised
+    let mut article = get_article_from_db(&db, &article.link)?;
+    if let Some(summary) = data_summary.summary {
+        if summary.summary != article.summary {
+            replace_summary(&mut article, summary, data_summary.summary_key);
+        }
+    }
+    article.summary_error = data_summary.summary_error;
+    article.fingerprint = text.as_deref().and_then(crate::dedupe::fingerprint);
+    article.updated = Some(Utc::now().to_rfc3339());
+    entry.apply_original(&mut article);
+    store_article_to_db(&db, &article)?;
+    store_page(
+        &db,
+        &article,
+        channel_title,
+        data.content.as_deref(),
+        text.as_deref(),
+    );
+    store_feed_content(&db, &article.link, entry.content.as_deref());
+    store_entry_state_to_db(&db, source, &entry, &article.link)
+}
+
+/// Replace the summary of an article, keeping the one it had.
+fn replace_summary(article: &mut Article, summary: Summary, summary_key: Option<String>) {
+    article.previous_summaries.push(SummaryVersion {
+        title: std::mem::replace(&mut article.title, summary.title),
+        summary: std::mem::replace(&mut article.summary, summary.summary),
+        summary_key: std::mem::replace(&mut article.summary_key, summary_key),
+        key_points: std::mem::replace(&mut article.key_points, summary.key_points),
+        tags: std::mem::replace(&mut article.tags, summary.tags),
+        clickbait_score: std::mem::replace(&mut article.clickbait_score, summary.clickbait_score),
+        replaced: Utc::now().to_rfc3339(),
+    });
+}
+
+/// Keep the readable content of an article's page and index its text for search.
+fn store_page(
+    db: &Db,
+    article: &Article,
+    channel_title: &str,
+    content: Option<&str>,
+    text: Option<&str>,
+) {
+    if let Some(content) = content {
+        if let Err(e) =
+            crate::content::store_content_to_db(db, ContentSource::Page, &article.link, content)
+        {
+            eprintln!("Error storing article content: {e}");
+        }
+    }
+    if let Err(e) =
+        crate::search::index_article(db, article, channel_title, text.unwrap_or_default())
+    {
+        eprintln!("Error indexing article: {e}");
+    }
+}
+
+/// Keep the content a feed entry came with, if any.
+fn store_feed_content(db: &Db, link: &str, content: Option<&str>) {
+    if let Some(content) = content {
+        if let Err(e) = crate::content::store_content_to_db(db, ContentSource::Feed, link, content)
+        {
+            eprintln!("Error storing feed content: {e}");
+        }
+    }
+}
+
+/// Link of the stored article one of these links leads to, directly or as an alias.
+fn find_article_link(db: &Db, links: &[&str]) -> Option<String> {
+    links.iter().find_map(|link| {
+        if db.contains_key(format!("article:{link}")).ok()? {
+            return Some((*link).to_string());
+        }
+        let target = db.get(format!("alias:{link}")).ok()??;
+        Some(String::from_utf8_lossy(&target).to_string())
+    })
+}
+
+/// Link of a recent article with the same story as a feed entry, by its content or title.
+fn find_duplicate(
+    db: &Db,
+    channel: &str,
+    title: &str,
+    fingerprint: Option<u64>,
+    published: &DateTime<Utc>,
+) -> Option<String> {
+    let window = chrono::Duration::days(DUPLICATE_WINDOW_DAYS);
+    let prefix = date_index_prefix();
+    let lower = format!("{prefix}{}", timestamp_key(&(*published - window)));
+    let upper = format!("{prefix}{}", timestamp_key(&(*published + window)));
+    db.range(lower..upper)
+        .keys()
+        .rev()
+        .filter_map(Result::ok)
+        .take(MAX_DUPLICATE_CANDIDATES)
+        .filter_map(|key| get_article_from_db(db, link_from_index_key(&key, &prefix)?).ok())
+        .find(|article| crate::dedupe::is_near_duplicate(channel, title, fingerprint, article))
+        .map(|article| article.link)
+}
+
+/// Function to add a feed entry with the same story to an article in the database.
+fn add_source_to_db(db: &Db, link: &str, source: ArticleSource) -> Result<(), Error> {
+    let mut article = get_article_from_db(db, link)?;
+    let alias = crate::dedupe::canonicalize(&source.link);
+    if alias != article.link && !article.aliases.contains(&alias) {
+        db.insert(format!("alias:{alias}"), link.as_bytes())?;
+        article.aliases.push(alias);
+    }
+    // Another entry of the same channel is just another way to the article
+    if source.channel != article.channel
+        && !article
+            .sources
+            .iter()
+            .any(|known| known.link == source.link)
+    {
+        article.sources.push(source);
+    }
+    store_article_to_db(db, &article)
+}
+
 // Function to retrieve a article from the database based on its link.
-fn get_article_from_db(db: &Db, link: &str) -> Result<Article, Box<dyn std::error::Error>> {
+fn get_article_from_db(db: &Db, link: &str) -> Result<Article, Error> {
     // Construct the key for the database lookup using the provided link.
     let key = format!("article:{link}");
 
@@ -245,56 +835,472 @@ fn get_article_from_db(db: &Db, link: &str) -> Result<Article, Box<dyn std::erro
             Ok(article)
         }
         // If no data is found, return an error.
-        None => Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Article not found",
-        ))),
+        None => Err(Error::NotFound("Article".to_string())),
     }
 }
 
-/// Function to store a article into the database.
-fn store_article_to_db(db: &Db, article: &Article) -> Result<(), Box<dyn std::error::Error>> {
+/// Version of the secondary index layout, bump to rebuild the indexes on startup.
+const INDEX_VERSION: &str = "1";
+
+/// How far apart in days articles can be published and still be found to be the same story, and
+/// how many articles in that window are compared.
+const DUPLICATE_WINDOW_DAYS: i64 = 2;
+const MAX_DUPLICATE_CANDIDATES: usize = 500;
+
+/// Version of the article records, bump to rewrite them on startup.
+const ARTICLE_SCHEMA_VERSION: &str = "2";
+
+/// Fixed width sort key for a published date, so keys order chronologically.
+fn timestamp_key(published: &DateTime<Utc>) -> String {
+    format!("{:020}", published.timestamp_millis().max(0))
+}
+
+/// Prefixes of the secondary indexes, each followed by a timestamp key and the link.
+fn date_index_prefix() -> String {
+    "idx:date:".to_string()
+}
+fn status_index_prefix(status: &ReadStatus) -> String {
+    format!("idx:status:{status:?}:")
+}
+fn channel_index_prefix(channel: &str) -> String {
+    format!("idx:channel:{channel}\0")
+}
+
+/// Secondary index keys pointing at an article.
+fn index_keys(article: &Article) -> Vec<String> {
+    let published = parse_date(&article.published).unwrap_or_default();
+    let suffix = format!("{}:{}", timestamp_key(&published), article.link);
+    vec![
+        format!("{}{suffix}", date_index_prefix()),
+        format!("{}{suffix}", status_index_prefix(&article.read_status)),
+        format!("{}{suffix}", channel_index_prefix(&article.channel)),
+    ]
+}
+
+/// Get the link out of a secondary index key.
+fn link_from_index_key<'a>(key: &'a [u8], prefix: &str) -> Option<&'a str> {
+    // Skip the timestamp key and its separator
+    let rest = key.get(prefix.len() + 21..)?;
+    std::str::from_utf8(rest).ok()
+}
+
+/// Function to store a article into the database, keeping its secondary indexes up to date.
+fn store_article_to_db(db: &Db, article: &Article) -> Result<(), Error> {
     let key = format!("article:{}", &article.link);
-    let ivec = serde_json::to_vec(&article)?;
-    db.insert(key, ivec)?;
+    let mut batch = Batch::default();
+    if let Some(old) = db.get(&key)? {
+        if let Ok(old) = serde_json::from_slice::<Article>(&old) {
+            for index_key in index_keys(&old) {
+                batch.remove(index_key.as_bytes());
+            }
+        }
+    }
+    for index_key in index_keys(article) {
+        batch.insert(index_key.as_bytes(), &[]);
+    }
+    batch.insert(key.as_bytes(), serde_json::to_vec(&article)?);
+    db.apply_batch(batch)?;
     db.flush()?;
     Ok(())
 }
 
+/// Function to remove a article from the database. The states of its feed entries are kept, so
+/// it is not imported again while they are still in the feed.
+pub fn delete_article_from_db(db: &Db, link: &str) -> Result<(), Error> {
+    remove_article(db, link, false)
+}
+
+fn remove_article(db: &Db, link: &str, forget_entries: bool) -> Result<(), Error> {
+    let key = format!("article:{link}");
+    let mut batch = Batch::default();
+    if let Some(old) = db.get(&key)? {
+        if let Ok(old) = serde_json::from_slice::<Article>(&old) {
+            for index_key in index_keys(&old) {
+                batch.remove(index_key.as_bytes());
+            }
+            for alias in &old.aliases {
+                batch.remove(format!("alias:{alias}").as_bytes());
+            }
+            if forget_entries {
+                let sources = old
+                    .sources
+                    .iter()
+                    .map(|source| (&source.channel, &source.guid));
+                for (channel, guid) in std::iter::once((&old.channel, &old.guid)).chain(sources) {
+                    if let Some(guid) = guid {
+                        batch.remove(entry_state_key(channel, guid).as_bytes());
+                    }
+                }
+            }
+        }
+    }
+    batch.remove(key.as_bytes());
+    db.apply_batch(batch)?;
+    crate::content::delete_content_from_db(db, link)?;
+    crate::search::remove_article(db, link)?;
+    Ok(())
+}
+
+/// Rebuild the secondary indexes if they were built by an older version, or not at all.
+pub fn rebuild_indexes(db: &Db) -> Result<bool, Error> {
+    if db.get("meta:index_version")?.as_deref() == Some(INDEX_VERSION.as_bytes()) {
+        return Ok(false);
+    }
+    for key in db.scan_prefix("idx:").keys() {
+        db.remove(key?)?;
+    }
+    for (_, value) in db.scan_prefix("article:").filter_map(Result::ok) {
+        if let Ok(article) = serde_json::from_slice::<Article>(&value) {
+            for index_key in index_keys(&article) {
+                db.insert(index_key, &[])?;
+            }
+        }
+    }
+    db.insert("meta:index_version", INDEX_VERSION)?;
+    db.flush()?;
+    Ok(true)
+}
+
+/// Rewrite the article records of an older version in the current schema, returning how many
+/// were rewritten. Feeds with articles stored before the feed's own fields were kept are fetched
+/// in full again, so the next poll fills them in.
+pub fn migrate_articles(db: &Db) -> Result<usize, Error> {
+    if db.get("meta:article_schema_version")?.as_deref() == Some(ARTICLE_SCHEMA_VERSION.as_bytes())
+    {
+        return Ok(0);
+    }
+
+    let mut migrated = 0;
+    let mut incomplete = HashSet::new();
+    for entry in db.scan_prefix("article:") {
+        let (key, value) = entry?;
+        let Ok(article) = serde_json::from_slice::<Article>(&value) else {
+            continue;
+        };
+        if article.original_title.is_none() {
+            incomplete.insert(article.channel.clone());
+        }
+        db.insert(key, serde_json::to_vec(&article)?)?;
+        migrated += 1;
+    }
+    for channel in &incomplete {
+        crate::feed::delete_feed_state_from_db(db, channel)?;
+    }
+
+    db.insert("meta:article_schema_version", ARTICLE_SCHEMA_VERSION)?;
+    db.flush()?;
+    Ok(migrated)
+}
+
+/// Links and published timestamps (in milliseconds) of a channel's articles, newest first.
+pub fn channel_articles(db: &Db, channel: &str) -> Vec<(i64, String)> {
+    let prefix = channel_index_prefix(channel);
+    db.scan_prefix(&prefix)
+        .keys()
+        .rev()
+        .filter_map(Result::ok)
+        .filter_map(|key| {
+            let timestamp = std::str::from_utf8(key.get(prefix.len()..prefix.len() + 20)?)
+                .ok()?
+                .parse()
+                .ok()?;
+            let link = link_from_index_key(&key, &prefix)?.to_string();
+            Some((timestamp, link))
+        })
+        .collect()
+}
+
+/// Remove every article belonging to a channel, returning how many were removed. Their feed
+/// entries are forgotten too, so they come back as new if the channel is subscribed to again.
+pub fn delete_channel_articles(db: &Db, channel: &str) -> Result<usize, Error> {
+    let links: Vec<String> = channel_articles(db, channel)
+        .into_iter()
+        .map(|(_, link)| link)
+        .collect();
+    for link in &links {
+        remove_article(db, link, true)?;
+    }
+    for key in db.scan_prefix(entry_state_key(channel, "")).keys() {
+        db.remove(key?)?;
+    }
+    db.flush()?;
+    Ok(links.len())
+}
+
 /// Struct to represent the full article with its associated channel.
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
-struct FullArticle {
-    link: String,
-    channel: crate::channel::Channel,
-    title: String,
-    published: String,
-    image: String,
-    summary: String,
-    read_status: ReadStatus,
+pub struct FullArticle {
+    pub link: String,
+    /// Where the article is read.
+    pub url: String,
+    pub channel: crate::channel::Channel,
+    pub title: String,
+    pub published: String,
+    pub image: String,
+    pub thumbnail: Option<String>,
+    pub summary: String,
+    pub summary_error: Option<String>,
+    pub key_points: Vec<String>,
+    pub tags: Vec<String>,
+    pub clickbait_score: Option<f32>,
+    pub original_title: Option<String>,
+    pub original_summary: Option<String>,
+    pub previous_summaries: Vec<SummaryVersion>,
+    pub authors: Vec<String>,
+    pub categories: Vec<String>,
+    pub sources: Vec<ArticleSource>,
+    pub updated: Option<String>,
+    pub read_status: ReadStatus,
 }
 
-/// Get articles from the database
+impl FullArticle {
+    fn new(article: Article, channel: crate::channel::Channel) -> Self {
+        Self {
+            url: article.url().to_string(),
+            link: article.link,
+            channel,
+            title: article.title,
+            published: article.published,
+            image: article.image,
+            thumbnail: article.thumbnail,
+            summary: article.summary,
+            summary_error: article.summary_error,
+            key_points: article.key_points,
+            tags: article.tags,
+            clickbait_score: article.clickbait_score,
+            original_title: article.original_title,
+            original_summary: article.original_summary,
+            previous_summaries: article.previous_summaries,
+            authors: article.authors,
+            categories: article.categories,
+            sources: article.sources,
+            updated: article.updated,
+            read_status: article.read_status,
+        }
+    }
+}
+
+/// Retrieve an article joined with its channel.
+pub fn get_full_article_from_db(db: &Db, link: &str) -> Result<FullArticle, Error> {
+    let article = get_article_from_db(db, link)?;
+    let channel = crate::channel::get_channel_from_db(db, &article.channel)?;
+    Ok(FullArticle::new(article, channel))
+}
+
+/// Parse a date filter, either RFC 3339 or a plain `YYYY-MM-DD` date.
+pub fn parse_date(date: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+        })
+        .map_err(|_| format!("'{date}' is not a valid date"))
+}
+
+/// Default and maximum page size of the articles listing.
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Query parameters of the articles listing.
+#[derive(Deserialize, Debug, Default)]
+pub struct ArticlesQuery {
+    pub status: Option<String>,
+    pub channel: Option<String>,
+    pub category: Option<String>,
+    pub since: Option<String>,
+    pub before: Option<String>,
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+}
+
+/// Filters of the articles listing that are checked per article.
+struct ArticleFilter {
+    status: Option<ReadStatus>,
+    channel: Option<String>,
+    category: Option<String>,
+}
+
+impl ArticleFilter {
+    fn matches(&self, article: &FullArticle) -> bool {
+        self.status
+            .as_ref()
+            .is_none_or(|status| *status == article.read_status)
+            && self
+                .channel
+                .as_ref()
+                .is_none_or(|channel| *channel == article.channel.rss_url)
+            && self
+                .category
+                .as_ref()
+                .is_none_or(|category| *category == article.channel.category)
+    }
+}
+
+/// Walk one secondary index newest first within the date bounds, starting after the cursor.
+/// Returns true if the page filled up.
+fn read_index_page(
+    db: &Db,
+    prefix: &str,
+    bounds: (&str, &str),
+    cursor: Option<&[u8]>,
+    filter: &ArticleFilter,
+    page: &mut Vec<(Vec<u8>, FullArticle)>,
+    limit: usize,
+) -> bool {
+    let lower = format!("{prefix}{}", bounds.0).into_bytes();
+    let mut upper = format!("{prefix}{}", bounds.1).into_bytes();
+    if let Some(cursor) = cursor.filter(|cursor| cursor.starts_with(prefix.as_bytes())) {
+        upper = upper.min(cursor.to_vec());
+    }
+    if lower >= upper {
+        return false;
+    }
+
+    for key in db.range(lower..upper).keys().rev().filter_map(Result::ok) {
+        let Some(link) = link_from_index_key(&key, prefix) else {
+            continue;
+        };
+        let Ok(article) = get_full_article_from_db(db, link) else {
+            continue;
+        };
+        if filter.matches(&article) {
+            page.push((key.to_vec(), article));
+            if page.len() >= limit {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Get a page of articles from the database, newest first or grouped by source
 #[allow(clippy::unused_async, clippy::module_name_repetitions)]
-pub async fn get_articles(db: Arc<Db>) -> impl IntoResponse {
-    let articles: Vec<FullArticle> = db
-        .scan_prefix("article:")
-        .filter_map(Result::ok)
-        .filter_map(|(_, value)| {
-            let article: Article = serde_json::from_slice(&value).ok()?;
-            let channel = crate::channel::get_channel_from_db(&db, &article.channel).ok()?;
-            Some(FullArticle {
-                link: article.link,
-                channel,
-                title: article.title,
-                published: article.published,
-                image: article.image,
-                summary: article.summary,
-                read_status: article.read_status,
-            })
+pub async fn get_articles(
+    QueryParams(query): QueryParams<ArticlesQuery>,
+    db: Arc<Db>,
+) -> Result<Json<serde_json::Value>, Error> {
+    // Parse the parameters
+    let status = query
+        .status
+        .as_deref()
+        .map(ReadStatus::from_str)
+        .transpose()
+        .map_err(Error::Validation)?;
+    let since = query
+        .since
+        .as_deref()
+        .map(parse_date)
+        .transpose()
+        .map_err(Error::Validation)?
+        .map_or_else(
+            || timestamp_key(&DateTime::default()),
+            |since| timestamp_key(&since),
+        );
+    let before = query
+        .before
+        .as_deref()
+        .map(parse_date)
+        .transpose()
+        .map_err(Error::Validation)?
+        .map_or_else(|| "~".to_string(), |before| timestamp_key(&before));
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|cursor| general_purpose::URL_SAFE_NO_PAD.decode(cursor))
+        .transpose()
+        .map_err(|e| Error::Parse(format!("cursor: {e}")))?;
+    let by_source = match query.sort.as_deref().map(str::to_lowercase).as_deref() {
+        None | Some("date") => false,
+        Some("source") => true,
+        Some(sort) => {
+            return Err(Error::Validation(format!(
+                "'{sort}' is not a valid sort mode"
+            )))
+        }
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let filter = ArticleFilter {
+        status,
+        channel: query.channel,
+        category: query.category,
+    };
+
+    let mut page = Vec::new();
+    let bounds = (since.as_str(), before.as_str());
+    let full = if by_source {
+        // Walk each channel's index in turn, in the same order the frontend sorts sources
+        let mut channels: Vec<String> = match &filter.channel {
+            Some(channel) => vec![channel.clone()],
+            None => db
+                .scan_prefix("channel:")
+                .filter_map(Result::ok)
+                .filter_map(|(_, value)| {
+                    serde_json::from_slice::<crate::channel::Channel>(&value).ok()
+                })
+                .filter(|channel| {
+                    filter
+                        .category
+                        .as_ref()
+                        .is_none_or(|category| *category == channel.category)
+                })
+                .map(|channel| channel.rss_url)
+                .collect(),
+        };
+        channels.sort();
+
+        // Resume from the channel the cursor is in
+        if let Some(cursor) = &cursor {
+            if let Some(position) = channels
+                .iter()
+                .position(|channel| cursor.starts_with(channel_index_prefix(channel).as_bytes()))
+            {
+                channels.drain(..position);
+            }
+        }
+
+        channels.iter().any(|channel| {
+            read_index_page(
+                &db,
+                &channel_index_prefix(channel),
+                bounds,
+                cursor.as_deref(),
+                &filter,
+                &mut page,
+                limit,
+            )
         })
-        .collect();
+    } else {
+        // Use the narrowest index available
+        let prefix = match (&filter.status, &filter.channel) {
+            (Some(status), _) => status_index_prefix(status),
+            (None, Some(channel)) => channel_index_prefix(channel),
+            (None, None) => date_index_prefix(),
+        };
+        read_index_page(
+            &db,
+            &prefix,
+            bounds,
+            cursor.as_deref(),
+            &filter,
+            &mut page,
+            limit,
+        )
+    };
 
-    Json(json!(articles))
+    let next_cursor = full
+        .then(|| page.last())
+        .flatten()
+        .map(|(key, _)| general_purpose::URL_SAFE_NO_PAD.encode(key));
+    let articles: Vec<FullArticle> = page.into_iter().map(|(_, article)| article).collect();
+
+    Ok(Json(
+        json!({"articles": articles, "next_cursor": next_cursor}),
+    ))
 }
 
 /// Move an article to a different read status
@@ -302,40 +1308,102 @@ pub async fn get_articles(db: Arc<Db>) -> impl IntoResponse {
 pub async fn update_article_status(
     Path((link, new_status)): Path<(String, String)>,
     db: Arc<Db>,
-) -> impl IntoResponse {
+) -> Result<Json<serde_json::Value>, Error> {
     // Decode link URI
-    let link: String = match urlencoding::decode(&link) {
-        Ok(link) => link.to_string(),
-        Err(e) => return Json(json!({"status": "error", "message": e.to_string()})),
-    };
+    let link = urlencoding::decode(&link).map_err(|e| Error::Parse(format!("link: {e}")))?;
 
-    // Try to get the article from the database
-    let mut article: Article = match get_article_from_db(&db, &link) {
-        Ok(article) => article,
-        Err(e) => {
-            return Json(
-                json!({"status": "error", "message": format!("Failed to get article from database: {e}")}),
-            )
-        }
-    };
+    // Get the article and the status to move it to
+    let mut article = get_article_from_db(&db, &link)?;
+    let new_status_enum = ReadStatus::from_str(&new_status).map_err(Error::Validation)?;
 
-    // Try to convert the new_status string to a ReadStatus
-    let new_status_enum = match ReadStatus::from_str(&new_status) {
-        Ok(status) => status,
-        Err(e) => {
-            return Json(
-                json!({"status": "error", "message": format!("Failed to convert new status to ReadStatus: {e}")}),
-            )
+    // Update the read status and store the article in the database
+    article.read_status = new_status_enum;
+    store_article_to_db(&db, &article)?;
+
+    Ok(Json(
+        json!({"status": "success", "message": "Article status updated successfully"}),
+    ))
+}
+
+/// What to re-summarise an article with, the configured model and prompt by default.
+#[derive(Deserialize, Debug, Default)]
+pub struct ResummarizeRequest {
+    pub model: Option<String>,
+    /// Name of a prompt template from the config.
+    pub prompt: Option<String>,
+}
+
+/// Scrape and summarise an article again, bypassing the summary cache and keeping the summary it
+/// replaces
+pub async fn resummarize_article(
+    Path(link): Path<String>,
+    db: Arc<Db>,
+    request: Option<Json<ResummarizeRequest>>,
+) -> Result<Json<serde_json::Value>, Error> {
+    // Decode link URI
+    let link = urlencoding::decode(&link)
+        .map_err(|e| Error::Parse(format!("link: {e}")))?
+        .to_string();
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+
+    let article = get_article_from_db(&db, &link)?;
+    let channel = crate::channel::get_channel_from_db(&db, &article.channel)?;
+    let (summarizer, prompt) = crate::summarizer::load_with(
+        &article.channel,
+        request.model.as_deref(),
+        request.prompt.as_deref(),
+    )
+    .map_err(Error::Validation)?;
+
+    // Summarise from the feed's title, like the first time
+    let title = article
+        .original_title
+        .clone()
+        .unwrap_or_else(|| article.title.clone());
+    let data = scrape_website(article.url()).await?;
+    let text = data
+        .main_content
+        .as_deref()
+        .map(crate::search::html_to_text);
+    let data_summary = summarise_page(
+        db.clone(),
+        summarizer.as_ref(),
+        &prompt,
+        &article.channel,
+        title,
+        data.main_content,
+        true,
+    )
+    .await;
+
+    // Keep the current summary when the new one failed
+    let summary = match (data_summary.summary, data_summary.summary_error) {
+        (Some(summary), None) => summary,
+        (_, error) => {
+            let error = error.unwrap_or_else(|| "No summary was produced".to_string());
+            return Err(Error::Llm(error));
         }
     };
 
-    // Update the read status and store the article in the database
-    article.read_status = new_status_enum;
-    if let Err(e) = store_article_to_db(&db, &article) {
-        return Json(
-            json!({"status": "error", "message": format!("Failed to store updated article in database: {e}")}),
-        );
+    // Read it again, as its status may have changed while the page was summarised
+    let mut article = get_article_from_db(&db, &link)?;
+    if summary.summary != article.summary {
+        replace_summary(&mut article, summary, data_summary.summary_key);
     }
+    article.summary_error = None;
+    article.fingerprint = text.as_deref().and_then(crate::dedupe::fingerprint);
+    store_article_to_db(&db, &article)?;
+    store_page(
+        &db,
+        &article,
+        &channel.title,
+        data.content.as_deref(),
+        text.as_deref(),
+    );
 
-    Json(json!({"status": "success", "message": "Article status updated successfully"}))
+    Ok(Json(json!({
+        "status": "success",
+        "message": "Article re-summarised successfully",
+        "article": FullArticle::new(article, channel),
+    })))
 }
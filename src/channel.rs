@@ -1,11 +1,16 @@
-use feed_rs::parser;
+use crate::{
+    error::Error,
+    extract::{JsonBody, QueryParams},
+};
+use axum::{extract::Path, response::Json};
 use image::{DynamicImage, GenericImageView};
 use piped::PipedClient;
 use reqwest::Client;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sled::Db;
-use std::{collections::HashMap, io::Cursor};
+use std::{collections::HashMap, sync::Arc};
 
 /// Struct to represent a channel.
 #[allow(clippy::module_name_repetitions)]
@@ -16,6 +21,10 @@ pub struct ChannelOptional {
     pub title: Option<String>,
     pub icon: Option<String>,
     pub dominant_color: Option<String>,
+    pub poll_interval: Option<u64>,
+    pub prompt: Option<String>,
+    /// Makes the feed by scraping the page at `rss_url`, for sites that have none.
+    pub scrape: Option<crate::generated::ScrapeSpec>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
@@ -25,10 +34,12 @@ pub struct Channel {
     pub title: String,
     pub icon: String,
     pub dominant_color: String,
+    #[serde(default)]
+    pub icon_thumbnail: Option<String>,
 }
 
 // Function to retrieve a channel from the database based on its link.
-pub fn get_channel_from_db(db: &Db, link: &str) -> Result<Channel, Box<dyn std::error::Error>> {
+pub fn get_channel_from_db(db: &Db, link: &str) -> Result<Channel, Error> {
     // Construct the key for the database lookup using the provided link.
     let key = format!("channel:{link}");
 
@@ -40,37 +51,41 @@ pub fn get_channel_from_db(db: &Db, link: &str) -> Result<Channel, Box<dyn std::
             Ok(channel)
         }
         // If no data is found, return an error.
-        None => Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Channel not found",
-        ))),
+        None => Err(Error::NotFound("Channel".to_string())),
     }
 }
 
 // Function to store a channel into the database.
-fn store_channel_to_db(
-    db: &Db,
-    channel: &Channel,
-    link: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+fn store_channel_to_db(db: &Db, channel: &Channel, link: &str) -> Result<(), Error> {
     let ivec = serde_json::to_vec(&channel)?;
     db.insert(format!("channel:{link}"), ivec)?;
     db.flush()?;
     Ok(())
 }
 
+// Function to remove a channel's feed state from the database. The channel itself is only removed
+// along with its articles, as those left are shown with it.
+fn delete_channel_from_db(db: &Db, link: &str, purge: bool) -> Result<(), Error> {
+    if purge {
+        db.remove(format!("channel:{link}"))?;
+    }
+    db.remove(format!("schedule:{link}"))?;
+    crate::feed::delete_feed_state_from_db(db, link)?;
+    crate::health::delete_health_from_db(db, link)?;
+    crate::generated::delete_first_seen_from_db(db, link)?;
+    db.flush()?;
+    Ok(())
+}
+
 /// Function to retrieve a channel from the database or create it if it does not exist.
 pub async fn get_channel_data(
     db: &Db,
     needs_fresh: bool,
     source: &ChannelOptional,
-) -> Result<ChannelOptional, Box<dyn std::error::Error>> {
+) -> Result<ChannelOptional, Error> {
     if needs_fresh {
         // Fetch the page feed.
-        let response = reqwest::get(source.rss_url.clone()).await?;
-        let bytes = response.bytes().await?;
-        let cursor = Cursor::new(bytes);
-        let feed = parser::parse(cursor)?;
+        let feed = crate::feed::download(&source.rss_url, source.scrape.as_ref()).await?;
 
         // Get first link or default to feed.id
         let feed_link = feed
@@ -79,9 +94,10 @@ pub async fn get_channel_data(
             .map_or(feed.id.clone(), |link| link.href.clone());
 
         // Get the base URL
-        let parsed_url = url::Url::parse(feed_link.as_str())?;
-        let base_url = if let Some(host) = parsed_url.host_str() {
-            format!("{}://{}", parsed_url.scheme(), host)
+        let parsed_url = url::Url::parse(feed_link.as_str())
+            .map_err(|e| Error::Feed(format!("Invalid link {feed_link}: {e}")))?;
+        let base_url = if parsed_url.host_str().is_some() {
+            parsed_url.origin().ascii_serialization()
         } else {
             feed_link.clone()
         };
@@ -89,15 +105,21 @@ pub async fn get_channel_data(
         // Download the webpage to parse the HTML content.
         let channel_url = base_url.clone();
         let is_youtube = base_url.contains("youtube.com");
-        let document = Html::parse_document(&reqwest::get(channel_url).await?.text().await?);
+        let scrape_error = |e: reqwest::Error| Error::Scrape(e.to_string());
+        let page = reqwest::get(channel_url)
+            .await
+            .map_err(scrape_error)?
+            .text()
+            .await
+            .map_err(scrape_error)?;
 
         // Get page title
         let mut title = if let Some(source_title) = source.title.clone() {
             source_title
-        } else if is_youtube && feed.title.is_some() {
-            feed.title.unwrap().content
+        } else if let (true, Some(feed_title)) = (is_youtube, feed.title) {
+            feed_title.content
         } else {
-            document
+            Html::parse_document(&page)
                 .select(&Selector::parse("title").unwrap())
                 .next()
                 .map(|element| element.inner_html())
@@ -108,15 +130,19 @@ pub async fn get_channel_data(
         let mut favicon = if let Some(source_icon) = source.icon.clone() {
             source_icon
         } else {
-            url::Url::parse(&base_url)?
-                .join("/favicon.ico")?
+            url::Url::parse(&base_url)
+                .and_then(|url| url.join("/favicon.ico"))
+                .map_err(|e| Error::Feed(format!("Invalid link {base_url}: {e}")))?
                 .to_string()
         };
         // Youtube specific title and icon with piped
         if is_youtube {
-            let channel_id = source.rss_url.split('=').last().unwrap().to_string();
+            let channel_id = source.rss_url.split('=').next_back().unwrap_or_default();
             let client = PipedClient::new(&Client::new(), "https://pipedapi.kavin.rocks");
-            let channel = client.channel_from_id(channel_id).await.unwrap();
+            let channel = client
+                .channel_from_id(channel_id)
+                .await
+                .map_err(|e| Error::Feed(format!("Failed to look up channel {channel_id}: {e}")))?;
 
             title = channel.name;
             favicon = channel.avatar_url;
@@ -128,8 +154,15 @@ pub async fn get_channel_data(
         } else {
             // Extract the dominant color from the image
             get_dominant_color(
-                &image::load_from_memory(&reqwest::get(&favicon).await?.bytes().await?)
-                    .map_err(|_| format!("Failed to decode the image from {}", &favicon))?,
+                &image::load_from_memory(
+                    &reqwest::get(&favicon)
+                        .await
+                        .map_err(scrape_error)?
+                        .bytes()
+                        .await
+                        .map_err(scrape_error)?,
+                )
+                .map_err(|_| Error::Scrape(format!("Failed to decode the image from {favicon}")))?,
             )
             .unwrap_or("#000000".to_string())
         };
@@ -139,6 +172,7 @@ pub async fn get_channel_data(
             rss_url: source.rss_url.clone(),
             category: source.category.clone().unwrap_or_default(),
             title,
+            icon_thumbnail: cache_icon(db, &favicon).await,
             icon: favicon,
             dominant_color,
         };
@@ -148,6 +182,7 @@ pub async fn get_channel_data(
             title: Some(channel.title.clone()),
             icon: Some(channel.icon.clone()),
             dominant_color: Some(channel.dominant_color.clone()),
+            ..source.clone()
         };
 
         // Store the newly constructed channel in the database.
@@ -163,6 +198,10 @@ pub async fn get_channel_data(
             title: source.title.clone().unwrap_or_default(),
             icon: source.icon.clone().unwrap_or_default(),
             dominant_color: source.dominant_color.clone().unwrap_or_default(),
+            icon_thumbnail: match &source.icon {
+                Some(icon) => cache_icon(db, icon).await,
+                None => None,
+            },
         };
         store_channel_to_db(db, &channel, &source.rss_url)?;
 
@@ -170,6 +209,20 @@ pub async fn get_channel_data(
     }
 }
 
+/// Keep a local copy of a channel's icon, returning the path it is served from.
+async fn cache_icon(db: &Db, icon: &str) -> Option<String> {
+    if icon.is_empty() {
+        return None;
+    }
+    match crate::media::cache_image(db, icon, crate::media::ICON_SIZE).await {
+        Ok(path) => Some(path),
+        Err(e) => {
+            eprintln!("Error caching icon {icon}: {e}");
+            None
+        }
+    }
+}
+
 /// Get the dominant color from an image.
 fn get_dominant_color(img: &DynamicImage) -> Option<String> {
     let mut color_count: HashMap<(u8, u8, u8), usize> = HashMap::new();
@@ -199,3 +252,170 @@ fn get_dominant_color(img: &DynamicImage) -> Option<String> {
         .max_by_key(|&(_, count)| count)
         .map(|((r, g, b), _)| format!("#{r:02x}{g:02x}{b:02x}"))
 }
+
+/// Fields of a channel that can be edited, unset fields are left unchanged.
+#[derive(Deserialize, Debug)]
+pub struct ChannelPatch {
+    pub category: Option<String>,
+    pub title: Option<String>,
+    pub icon: Option<String>,
+    pub dominant_color: Option<String>,
+    pub poll_interval: Option<u64>,
+    pub prompt: Option<String>,
+}
+
+impl ChannelPatch {
+    /// Apply the set fields to a channel.
+    fn apply(self, feed: &mut ChannelOptional) {
+        if self.category.is_some() {
+            feed.category = self.category;
+        }
+        if self.title.is_some() {
+            feed.title = self.title;
+        }
+        if self.icon.is_some() {
+            feed.icon = self.icon;
+            // A new icon needs its colour recomputed unless one was given
+            feed.dominant_color = None;
+        }
+        if self.dominant_color.is_some() {
+            feed.dominant_color = self.dominant_color;
+        }
+        if self.poll_interval.is_some() {
+            feed.poll_interval = self.poll_interval;
+        }
+        if self.prompt.is_some() {
+            feed.prompt = self.prompt;
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeleteOptions {
+    #[serde(default)]
+    pub purge: bool,
+}
+
+/// Pull a channel's articles in the background. It is scheduled first, so the regular pull
+/// does not pick it up at the same time.
+fn pull_in_background(db: Arc<Db>, channel: ChannelOptional) -> Result<(), Error> {
+    crate::schedule::record_poll(&db, &channel)?;
+    tokio::spawn(async move {
+        if let Err(e) = crate::articles::process_source(&channel.rss_url, db.clone()).await {
+            eprintln!("Error processing source {}: {e}", channel.rss_url);
+        }
+        if let Err(e) = crate::schedule::record_poll(&db, &channel) {
+            eprintln!("Error scheduling source {}: {e}", channel.rss_url);
+        }
+    });
+    Ok(())
+}
+
+/// Subscribe to a new channel
+pub async fn add_channel(
+    JsonBody(source): JsonBody<ChannelOptional>,
+    db: Arc<Db>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let exists = |config: &crate::config::Config| {
+        config.rss.iter().any(|feed| feed.rss_url == source.rss_url)
+    };
+    if exists(&crate::config::load()?) {
+        return Err(Error::Validation("Channel already exists".to_string()));
+    }
+    crate::schedule::validate_poll_interval(source.poll_interval)?;
+
+    // Make sure the feed is actually a feed, and get its data, before subscribing to it
+    let channel = get_channel_data(&db, true, &source).await?;
+
+    // Add it to the config, unless it was added in the meantime
+    let new_source = channel.clone();
+    let added = crate::config::update(|config| {
+        if exists(config) {
+            false
+        } else {
+            config.rss.push(new_source);
+            true
+        }
+    })?;
+    if !added {
+        return Err(Error::Validation("Channel already exists".to_string()));
+    }
+
+    pull_in_background(db, channel.clone())?;
+    Ok(Json(
+        json!({"status": "success", "message": "Channel added successfully", "channel": channel}),
+    ))
+}
+
+/// Edit an existing channel
+pub async fn edit_channel(
+    Path(rss_url): Path<String>,
+    JsonBody(patch): JsonBody<ChannelPatch>,
+    db: Arc<Db>,
+) -> Result<Json<serde_json::Value>, Error> {
+    // Decode link URI
+    let rss_url = urlencoding::decode(&rss_url).map_err(|e| Error::Parse(format!("link: {e}")))?;
+
+    let mut source = crate::config::load()?
+        .rss
+        .into_iter()
+        .find(|feed| feed.rss_url == rss_url)
+        .ok_or(Error::NotFound("Channel".to_string()))?;
+    crate::schedule::validate_poll_interval(patch.poll_interval)?;
+    patch.apply(&mut source);
+
+    // Only a channel missing its title, icon or colour has to be fetched again, and it is before
+    // the edit is saved so a failure leaves the channel as it was
+    let needs_fresh =
+        source.title.is_none() || source.icon.is_none() || source.dominant_color.is_none();
+    let channel = get_channel_data(&db, needs_fresh, &source).await?;
+
+    // Save the edit to the config
+    let stored = channel.clone();
+    let edited = crate::config::update(|config| {
+        let feed = config.rss.iter_mut().find(|feed| feed.rss_url == rss_url)?;
+        *feed = stored;
+        Some(())
+    })?;
+    if edited.is_none() {
+        return Err(Error::NotFound("Channel".to_string()));
+    }
+
+    pull_in_background(db, channel.clone())?;
+    Ok(Json(
+        json!({"status": "success", "message": "Channel updated successfully", "channel": channel}),
+    ))
+}
+
+/// Unsubscribe from a channel, optionally purging its articles
+#[allow(clippy::unused_async)]
+pub async fn delete_channel(
+    Path(rss_url): Path<String>,
+    QueryParams(options): QueryParams<DeleteOptions>,
+    db: Arc<Db>,
+) -> Result<Json<serde_json::Value>, Error> {
+    // Decode link URI
+    let rss_url = urlencoding::decode(&rss_url).map_err(|e| Error::Parse(format!("link: {e}")))?;
+
+    // Remove it from the config
+    let removed = crate::config::update(|config| {
+        let count = config.rss.len();
+        config.rss.retain(|feed| feed.rss_url != rss_url);
+        count != config.rss.len()
+    })?;
+    if !removed {
+        return Err(Error::NotFound("Channel".to_string()));
+    }
+
+    // Remove it from the database
+    delete_channel_from_db(&db, &rss_url, options.purge)?;
+    let purged = if options.purge {
+        crate::articles::delete_channel_articles(&db, &rss_url)?
+    } else {
+        0
+    };
+
+    Ok(Json(
+        json!({"status": "success", "message": format!("Channel deleted successfully, purged {purged} articles")}),
+    ))
+}
@@ -0,0 +1,57 @@
+use crate::{
+    channel::ChannelOptional,
+    error::Error,
+    retention::RetentionRule,
+    summarizer::{PromptTemplate, SummarizerConfig},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{read_to_string, write},
+    sync::Mutex,
+};
+
+/// Path of the subscription config file.
+const CONFIG_PATH: &str = "feeds.toml";
+
+/// Guards read-modify-write cycles on the config file.
+static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct Config {
+    #[serde(default)]
+    pub rss: Vec<ChannelOptional>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retention: Vec<RetentionRule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summarizer: Option<SummarizerConfig>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prompts: BTreeMap<String, PromptTemplate>,
+}
+
+/// Read and parse the config file.
+pub fn load() -> Result<Config, Error> {
+    let contents = read_to_string(CONFIG_PATH)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// Serialize and write the config file.
+fn save(config: &Config) -> Result<(), Error> {
+    let toml = toml::to_string(config)?;
+    write(CONFIG_PATH, toml)?;
+    Ok(())
+}
+
+/// Apply a modification to the config file, only writing it back if something changed.
+pub fn update<T>(modify: impl FnOnce(&mut Config) -> T) -> Result<T, Error> {
+    let _guard = CONFIG_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let mut config = load()?;
+    let before = toml::to_string(&config)?;
+    let result = modify(&mut config);
+    if toml::to_string(&config)? != before {
+        save(&config)?;
+    }
+    Ok(result)
+}
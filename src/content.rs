@@ -0,0 +1,105 @@
+use crate::{error::Error, extract::QueryParams};
+use axum::{
+    extract::Path,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::Deserialize;
+use sled::Db;
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+/// Where the content of an article came from.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentSource {
+    /// The readable content scraped from the article's page.
+    #[default]
+    Page,
+    /// The content the feed entry came with.
+    Feed,
+}
+
+fn content_key(source: ContentSource, link: &str) -> String {
+    match source {
+        ContentSource::Page => format!("content:{link}"),
+        ContentSource::Feed => format!("feedcontent:{link}"),
+    }
+}
+
+/// Strip scripts, styles, event handlers and anything else unsafe from scraped HTML.
+pub fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .link_rel(Some("noopener noreferrer"))
+        .clean(html)
+        .to_string()
+}
+
+/// Wrap plain text such as a transcript into paragraphs of HTML.
+pub fn text_to_html(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| format!("<p>{}</p>", html_escape::encode_text(line)))
+        .collect()
+}
+
+/// Function to store an article's readable content into the database, sanitized and compressed.
+pub fn store_content_to_db(
+    db: &Db,
+    source: ContentSource,
+    link: &str,
+    html: &str,
+) -> Result<(), Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(sanitize(html).as_bytes())?;
+    db.insert(content_key(source, link), encoder.finish()?)?;
+    db.flush()?;
+    Ok(())
+}
+
+/// Function to retrieve an article's readable content from the database, if it was stored.
+pub fn get_content_from_db(
+    db: &Db,
+    source: ContentSource,
+    link: &str,
+) -> Result<Option<String>, Error> {
+    let Some(ivec) = db.get(content_key(source, link))? else {
+        return Ok(None);
+    };
+    let mut html = String::new();
+    GzDecoder::new(&ivec[..]).read_to_string(&mut html)?;
+    Ok(Some(html))
+}
+
+/// Function to remove all of an article's content from the database.
+pub fn delete_content_from_db(db: &Db, link: &str) -> Result<(), Error> {
+    db.remove(content_key(ContentSource::Page, link))?;
+    db.remove(content_key(ContentSource::Feed, link))?;
+    Ok(())
+}
+
+/// Query parameters of the content of an article.
+#[derive(Deserialize, Debug, Default)]
+pub struct ContentQuery {
+    #[serde(default)]
+    pub source: ContentSource,
+}
+
+/// Get the stored content of an article as HTML, from its page unless the feed's is asked for
+#[allow(clippy::unused_async)]
+pub async fn get_content(
+    Path(link): Path<String>,
+    QueryParams(query): QueryParams<ContentQuery>,
+    db: Arc<Db>,
+) -> Result<Response, Error> {
+    // Decode link URI
+    let link = urlencoding::decode(&link).map_err(|e| Error::Parse(format!("link: {e}")))?;
+
+    let html = get_content_from_db(&db, query.source, &link)?
+        .ok_or(Error::NotFound("Content of article".to_string()))?;
+    Ok(([(header::CONTENT_TYPE, "text/html; charset=utf-8")], html).into_response())
+}
@@ -0,0 +1,176 @@
+use crate::articles::Article;
+use std::collections::HashSet;
+
+/// Query parameters that only track where a visitor came from.
+const TRACKING_PARAMS: [&str; 11] = [
+    "ref", "ref_src", "ref_url", "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid",
+    "igshid", "yclid",
+];
+/// Words per shingle of the content fingerprint.
+const SHINGLE_WORDS: usize = 3;
+/// Fewest words of content worth fingerprinting, shorter texts say too little to compare.
+const MIN_FINGERPRINT_WORDS: usize = 100;
+/// Most bits two fingerprints can differ in for their contents to be near duplicates.
+const MAX_FINGERPRINT_DISTANCE: u32 = 3;
+/// Fewest words in a title worth comparing, and how alike two titles have to be.
+const MIN_TITLE_WORDS: usize = 5;
+const MIN_TITLE_SIMILARITY: f64 = 0.8;
+
+/// Normalise a link so the same page reached in different ways gets one key: https, no
+/// fragment, no tracking parameters and no trailing slash. It is only for finding articles by,
+/// pages are still opened and scraped at the link as given.
+pub fn canonicalize(link: &str) -> String {
+    let link = link.trim();
+    let Ok(mut url) = url::Url::parse(link) else {
+        return link.to_string();
+    };
+    if url.scheme() == "http" {
+        // Only fails for schemes that cannot change into each other
+        let _ = url.set_scheme("https");
+    }
+    url.set_fragment(None);
+
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_lowercase();
+            !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str())
+        })
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    let path = url.path().to_string();
+    if path.len() > 1 && path.ends_with('/') {
+        url.set_path(path.trim_end_matches('/'));
+    }
+    url.to_string()
+}
+
+// 64 bit FNV-1a, stable across Rust releases unlike the standard library's hasher
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// SimHash of the shingles of a text, close texts get fingerprints that differ in few bits.
+/// Texts too short to compare get none.
+pub fn fingerprint(text: &str) -> Option<u64> {
+    let words = crate::search::tokenize(text);
+    if words.len() < MIN_FINGERPRINT_WORDS {
+        return None;
+    }
+
+    let mut weights = [0i64; 64];
+    for shingle in words.windows(SHINGLE_WORDS) {
+        let hash = fnv1a(&shingle.join(" "));
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    Some(
+        weights
+            .iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight > 0)
+            .fold(0, |fingerprint, (bit, _)| fingerprint | 1 << bit),
+    )
+}
+
+/// Jaccard similarity of the words of two titles, if both are long enough to compare.
+#[allow(clippy::cast_precision_loss)]
+fn title_similarity(a: &str, b: &str) -> Option<f64> {
+    let a: HashSet<String> = crate::search::tokenize(a).into_iter().collect();
+    let b: HashSet<String> = crate::search::tokenize(b).into_iter().collect();
+    if a.len() < MIN_TITLE_WORDS || b.len() < MIN_TITLE_WORDS {
+        return None;
+    }
+    Some(a.intersection(&b).count() as f64 / a.union(&b).count() as f64)
+}
+
+/// Whether a story from a channel with a title and content fingerprint is the same as an article.
+pub fn is_near_duplicate(
+    channel: &str,
+    title: &str,
+    fingerprint: Option<u64>,
+    article: &Article,
+) -> bool {
+    let same_content = fingerprint
+        .zip(article.fingerprint)
+        .is_some_and(|(a, b)| (a ^ b).count_ones() <= MAX_FINGERPRINT_DISTANCE);
+    // Titles within a channel follow patterns, like a numbered series, so only compare across
+    let article_title = article.original_title.as_deref().unwrap_or(&article.title);
+    let same_title = channel != article.channel
+        && title_similarity(title, article_title)
+            .is_some_and(|similarity| similarity >= MIN_TITLE_SIMILARITY);
+    same_content || same_title
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonicalize_upgrades_to_https_and_drops_fragments() {
+        assert_eq!(
+            canonicalize("http://example.com/post#comments"),
+            "https://example.com/post"
+        );
+    }
+
+    #[test]
+    fn canonicalize_keeps_ports_and_the_root_path() {
+        assert_eq!(
+            canonicalize("http://localhost:8080/"),
+            "https://localhost:8080/"
+        );
+        assert_eq!(canonicalize("https://example.com"), "https://example.com/");
+    }
+
+    #[test]
+    fn canonicalize_strips_trailing_slashes() {
+        assert_eq!(
+            canonicalize("https://example.com/a/post//"),
+            "https://example.com/a/post"
+        );
+    }
+
+    #[test]
+    fn canonicalize_removes_tracking_parameters_only() {
+        assert_eq!(
+            canonicalize(
+                "https://example.com/p?utm_source=rss&id=5&UTM_Medium=x&fbclid=abc&ref=hn"
+            ),
+            "https://example.com/p?id=5"
+        );
+        assert_eq!(
+            canonicalize("https://example.com/p?utm_campaign=a"),
+            "https://example.com/p"
+        );
+    }
+
+    #[test]
+    fn canonicalize_gives_one_key_for_equivalent_queries() {
+        assert_eq!(
+            canonicalize("https://example.com/search?q=a%20b"),
+            canonicalize("https://example.com/search?q=a+b&utm_source=x")
+        );
+    }
+
+    #[test]
+    fn canonicalize_leaves_what_it_cannot_parse() {
+        assert_eq!(canonicalize("  not a url  "), "not a url");
+        assert_eq!(canonicalize("urn:uuid:1234"), "urn:uuid:1234");
+    }
+
+    #[test]
+    fn canonicalize_is_idempotent() {
+        let once = canonicalize("http://Example.com/a/?b=c d&utm_term=x#top");
+        assert_eq!(canonicalize(&once), once);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{channel::ChannelOptional, error::Error, extract::JsonBody};
+use axum::response::Json;
+use feed_rs::model::{Feed, FeedType};
+use futures::{stream, StreamExt};
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use url::Url;
+
+/// Link types a page advertises its feeds with.
+const FEED_TYPES: [&str; 3] = [
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+];
+/// Paths feeds are commonly found at, tried when a page advertises none.
+const COMMON_PATHS: [&str; 3] = ["/feed", "/rss.xml", "/atom.xml"];
+/// How many candidates are checked at once.
+const CONCURRENT_CHECKS: usize = 4;
+
+#[derive(Deserialize, Debug)]
+pub struct DiscoverRequest {
+    /// Any page of the site, with or without a scheme.
+    pub url: String,
+}
+
+/// A feed found for a site, ready to subscribe to.
+#[derive(Serialize, Debug)]
+struct DiscoveredFeed {
+    #[serde(flatten)]
+    channel: ChannelOptional,
+    format: &'static str,
+}
+
+/// A link that might be a feed, with the title the page gave it.
+struct Candidate {
+    url: String,
+    title: Option<String>,
+}
+
+fn format_name(feed_type: &FeedType) -> &'static str {
+    match feed_type {
+        FeedType::Atom => "atom",
+        FeedType::JSON => "json",
+        FeedType::RSS0 | FeedType::RSS1 | FeedType::RSS2 => "rss",
+    }
+}
+
+fn discovered(rss_url: String, title: Option<String>, feed: &Feed) -> DiscoveredFeed {
+    let title = feed
+        .title
+        .as_ref()
+        .map(|title| title.content.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .or(title);
+    DiscoveredFeed {
+        channel: ChannelOptional {
+            category: None,
+            rss_url,
+            title,
+            icon: None,
+            dominant_color: None,
+            poll_interval: None,
+            prompt: None,
+            scrape: None,
+        },
+        format: format_name(&feed.feed_type),
+    }
+}
+
+/// The id of the YouTube channel a channel URL is for, if it names it directly.
+fn youtube_channel_id(url: &Url) -> Option<String> {
+    let mut segments = url.path_segments()?;
+    (segments.next()? == "channel")
+        .then(|| segments.next())
+        .flatten()
+        .filter(|id| id.starts_with("UC"))
+        .map(String::from)
+}
+
+/// Find the id of the YouTube channel a page belongs to, for handle and custom URLs.
+fn find_youtube_channel_id(document: &Html, body: &str) -> Option<String> {
+    let selector = Selector::parse("link[rel=canonical], meta[itemprop=identifier]").ok()?;
+    document
+        .select(&selector)
+        .filter_map(|element| {
+            let value = element.value();
+            value.attr("href").or_else(|| value.attr("content"))
+        })
+        .find_map(|link| {
+            let id = link.rsplit('/').next()?;
+            id.starts_with("UC").then(|| id.to_string())
+        })
+        .or_else(|| {
+            // Pages rendered by script only name it in their data
+            let start = body.find("\"channelId\":\"")? + "\"channelId\":\"".len();
+            let id = &body[start..start + body[start..].find('"')?];
+            id.starts_with("UC").then(|| id.to_string())
+        })
+}
+
+fn youtube_feed(channel_id: &str) -> Candidate {
+    Candidate {
+        url: format!("https://www.youtube.com/feeds/videos.xml?channel_id={channel_id}"),
+        title: None,
+    }
+}
+
+/// The feeds a page links to, resolved against the page.
+fn advertised_feeds(document: &Html, page: &Url) -> Vec<Candidate> {
+    let Ok(selector) = Selector::parse("link[rel~=alternate][type][href]") else {
+        return Vec::new();
+    };
+    document
+        .select(&selector)
+        .filter(|element| {
+            element
+                .value()
+                .attr("type")
+                .is_some_and(|kind| FEED_TYPES.contains(&kind.trim().to_lowercase().as_str()))
+        })
+        .filter_map(|element| {
+            let href = element.value().attr("href")?;
+            Some(Candidate {
+                url: page.join(href.trim()).ok()?.to_string(),
+                title: element
+                    .value()
+                    .attr("title")
+                    .map(|title| title.trim().to_string())
+                    .filter(|title| !title.is_empty()),
+            })
+        })
+        .collect()
+}
+
+/// Download a candidate and keep it if it is a feed.
+async fn check(candidate: Candidate) -> Option<DiscoveredFeed> {
+    let feed = crate::feed::download(&candidate.url, None).await.ok()?;
+    Some(discovered(candidate.url, candidate.title, &feed))
+}
+
+/// The links on a page that might be feeds: the page itself if it is one, the YouTube channel
+/// it belongs to, the feeds it advertises or else the paths feeds are commonly at.
+async fn page_candidates(page: &Url, is_youtube: bool) -> Result<Vec<Candidate>, Error> {
+    let scrape_error = |e: reqwest::Error| Error::Scrape(format!("{page}: {e}"));
+    let body = reqwest::get(page.clone())
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(scrape_error)?
+        .text()
+        .await
+        .map_err(scrape_error)?;
+    if crate::feed::parse(page.as_str(), body.as_bytes()).is_ok() {
+        return Ok(vec![Candidate {
+            url: page.to_string(),
+            title: None,
+        }]);
+    }
+
+    let document = Html::parse_document(&body);
+    if let Some(channel_id) = is_youtube
+        .then(|| find_youtube_channel_id(&document, &body))
+        .flatten()
+    {
+        return Ok(vec![youtube_feed(&channel_id)]);
+    }
+    let advertised = advertised_feeds(&document, page);
+    if !advertised.is_empty() {
+        return Ok(advertised);
+    }
+    Ok(COMMON_PATHS
+        .iter()
+        .filter_map(|path| page.join(path).ok())
+        .map(|url| Candidate {
+            url: url.to_string(),
+            title: None,
+        })
+        .collect())
+}
+
+/// Find the feeds of a site from any of its pages
+pub async fn discover_feeds(
+    JsonBody(request): JsonBody<DiscoverRequest>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let input = request.url.trim();
+    let with_scheme = if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://{input}")
+    };
+    let page = Url::parse(&with_scheme)
+        .map_err(|e| Error::Validation(format!("'{input}' is not a valid URL: {e}")))?;
+    let is_youtube = page
+        .host_str()
+        .is_some_and(|host| host == "youtube.com" || host.ends_with(".youtube.com"));
+
+    // Channel URLs with the id in them need no lookup
+    let mut candidates = match is_youtube.then(|| youtube_channel_id(&page)).flatten() {
+        Some(channel_id) => vec![youtube_feed(&channel_id)],
+        None => page_candidates(&page, is_youtube).await?,
+    };
+    let mut seen = HashSet::new();
+    candidates.retain(|candidate| seen.insert(candidate.url.clone()));
+
+    let feeds: Vec<DiscoveredFeed> = stream::iter(candidates)
+        .map(check)
+        .buffered(CONCURRENT_CHECKS)
+        .filter_map(|feed| async move { feed })
+        .collect()
+        .await;
+    if feeds.is_empty() {
+        return Err(Error::NotFound(format!("Feed for {page}")));
+    }
+    Ok(Json(json!({"status": "success", "feeds": feeds})))
+}
@@ -0,0 +1,98 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+
+/// Everything that can go wrong, grouped by whose fault it is so the API can answer with the
+/// right status code.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A feed could not be downloaded or parsed.
+    #[error("Failed to fetch feed: {0}")]
+    Feed(String),
+    /// A page or image could not be downloaded or read.
+    #[error("Failed to scrape: {0}")]
+    Scrape(String),
+    /// A summarisation or image model failed or answered with something unusable.
+    #[error("Model failed: {0}")]
+    Llm(String),
+    /// Something sent to the API could not be parsed.
+    #[error("Failed to parse {0}")]
+    Parse(String),
+    /// The database or the config file could not be read or written.
+    #[error("Storage error: {0}")]
+    Storage(String),
+    /// What a request refers to does not exist.
+    #[error("{0} not found")]
+    NotFound(String),
+    /// A request asked for something that cannot be done.
+    #[error("{0}")]
+    Validation(String),
+}
+
+impl Error {
+    fn status(&self) -> StatusCode {
+        match self {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Parse(_) | Error::Validation(_) => StatusCode::BAD_REQUEST,
+            Error::Feed(_) | Error::Scrape(_) | Error::Llm(_) => StatusCode::BAD_GATEWAY,
+            Error::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Feed(_) => "feed",
+            Error::Scrape(_) => "scrape",
+            Error::Llm(_) => "llm",
+            Error::Parse(_) => "parse",
+            Error::Storage(_) => "storage",
+            Error::NotFound(_) => "not_found",
+            Error::Validation(_) => "validation",
+        }
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let body = json!({"status": "error", "error": self.kind(), "message": self.to_string()});
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+impl From<sled::Error> for Error {
+    fn from(e: sled::Error) -> Self {
+        Error::Storage(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Storage(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Storage(e.to_string())
+    }
+}
+
+impl From<async_openai::error::OpenAIError> for Error {
+    fn from(e: async_openai::error::OpenAIError) -> Self {
+        Error::Llm(e.to_string())
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Storage(format!("Invalid config: {e}"))
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Error::Storage(e.to_string())
+    }
+}
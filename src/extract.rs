@@ -0,0 +1,66 @@
+use crate::error::Error;
+use async_trait::async_trait;
+use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        FromRequest, FromRequestParts, Query,
+    },
+    http::{request::Parts, Request},
+    Json,
+};
+use serde::de::DeserializeOwned;
+
+/// What was wrong with a request, without the rejection's own "Failed to..." wording.
+fn cause(rejection: &(dyn std::error::Error + 'static)) -> String {
+    let mut cause = rejection;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
+
+/// A JSON request body, rejected with an [`Error`] like every other API error rather than with
+/// axum's plain text.
+#[derive(Debug)]
+pub struct JsonBody<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for JsonBody<T>
+where
+    Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = Error;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(request, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(e @ JsonRejection::JsonSyntaxError(_)) => {
+                Err(Error::Parse(format!("body: {}", cause(&e))))
+            }
+            Err(e) => Err(Error::Validation(cause(&e))),
+        }
+    }
+}
+
+/// Query parameters, rejected with an [`Error`] like every other API error rather than with
+/// axum's plain text.
+#[derive(Debug)]
+pub struct QueryParams<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for QueryParams<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Query::<T>::from_request_parts(parts, state)
+            .await
+            .map(|Query(value)| Self(value))
+            .map_err(|e: QueryRejection| Error::Parse(format!("query: {}", cause(&e))))
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{error::Error, generated::ScrapeSpec, health::Pull};
+use chrono::{DateTime, Duration, Utc};
+use feed_rs::{model::Feed, parser};
+use reqwest::{
+    header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::io::Cursor;
+
+/// Longest a feed's caching hints can postpone fetching it.
+const MAX_SKIP_MINUTES: i64 = 24 * 60;
+
+/// HTTP cache validators and freshness of a feed from its last successful fetch.
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Debug)]
+pub struct FeedState {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub next_fetch: Option<String>,
+}
+
+/// Function to retrieve a feed's cache state from the database, defaulting to an empty state.
+fn get_feed_state_from_db(db: &Db, rss_url: &str) -> Result<FeedState, Error> {
+    match db.get(format!("feedstate:{rss_url}"))? {
+        Some(ivec) => Ok(serde_json::from_slice(&ivec)?),
+        None => Ok(FeedState::default()),
+    }
+}
+
+/// Function to store a feed's cache state into the database.
+pub fn store_feed_state_to_db(db: &Db, rss_url: &str, state: &FeedState) -> Result<(), Error> {
+    let ivec = serde_json::to_vec(state)?;
+    db.insert(format!("feedstate:{rss_url}"), ivec)?;
+    db.flush()?;
+    Ok(())
+}
+
+/// Function to remove a feed's cache state from the database.
+pub fn delete_feed_state_from_db(db: &Db, rss_url: &str) -> Result<(), Error> {
+    db.remove(format!("feedstate:{rss_url}"))?;
+    Ok(())
+}
+
+/// Download and parse a feed unconditionally, generating it from the page if it is scraped.
+pub async fn download(rss_url: &str, scrape: Option<&ScrapeSpec>) -> Result<Feed, Error> {
+    let fetch_error = |e: reqwest::Error| Error::Feed(format!("{rss_url}: {e}"));
+    let response = reqwest::get(rss_url)
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(fetch_error)?;
+    let bytes = response.bytes().await.map_err(fetch_error)?;
+    parse_body(None, rss_url, scrape, &bytes)
+}
+
+/// Parse a downloaded feed.
+pub fn parse(rss_url: &str, bytes: &[u8]) -> Result<Feed, Error> {
+    parser::parse(Cursor::new(bytes)).map_err(|e| Error::Feed(format!("{rss_url}: {e}")))
+}
+
+/// Parse a downloaded feed, or the page a scraped feed is generated from.
+fn parse_body(
+    db: Option<&Db>,
+    rss_url: &str,
+    scrape: Option<&ScrapeSpec>,
+    bytes: &[u8],
+) -> Result<Feed, Error> {
+    match scrape {
+        Some(spec) => {
+            let html = String::from_utf8_lossy(bytes);
+            let rss = crate::generated::render(db, rss_url, spec, &html)?;
+            parse(rss_url, rss.as_bytes())
+        }
+        None => parse(rss_url, bytes),
+    }
+}
+
+/// Get the `max-age` from a Cache-Control header, in seconds.
+fn parse_max_age(cache_control: &str) -> Option<i64> {
+    cache_control.split(',').find_map(|directive| {
+        directive
+            .trim()
+            .strip_prefix("max-age=")
+            .and_then(|seconds| seconds.trim_matches('"').parse().ok())
+    })
+}
+
+/// Work out when the feed is next worth fetching from its caching hints, if it gave any.
+fn next_fetch(max_age: Option<i64>, ttl_minutes: Option<u32>) -> Option<String> {
+    let max_age = max_age.map(Duration::seconds);
+    let ttl = ttl_minutes.map(|ttl| Duration::minutes(ttl.into()));
+    let skip = max_age.max(ttl)?.min(Duration::minutes(MAX_SKIP_MINUTES));
+    (skip > Duration::zero()).then(|| (Utc::now() + skip).to_rfc3339())
+}
+
+/// Fetch a feed using the cache validators from its last fetch.
+///
+/// Returns `None` when the feed is not due yet or the server reports it unchanged. Otherwise
+/// the new cache state is returned alongside the feed, to be stored with
+/// [`store_feed_state_to_db`] once the feed has been processed. How the request went is noted
+/// in `pull`.
+pub async fn fetch_if_changed(
+    db: &Db,
+    rss_url: &str,
+    scrape: Option<&ScrapeSpec>,
+    pull: &mut Pull,
+) -> Result<Option<(Feed, FeedState)>, Error> {
+    let mut state = get_feed_state_from_db(db, rss_url)?;
+
+    // Skip feeds that asked not to be fetched again yet
+    if let Some(next_fetch) = state
+        .next_fetch
+        .as_deref()
+        .and_then(|next_fetch| DateTime::parse_from_rfc3339(next_fetch).ok())
+    {
+        if Utc::now() < next_fetch {
+            return Ok(None);
+        }
+    }
+
+    // Send the validators along so the server can answer with 304 Not Modified
+    let mut request = Client::new().get(rss_url);
+    if let Some(etag) = &state.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &state.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    let fetch_error = |e: reqwest::Error| Error::Feed(format!("{rss_url}: {e}"));
+    pull.fetched = true;
+    let response = request.send().await.map_err(fetch_error)?;
+    pull.responded(response.status().as_u16());
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let max_age = header(CACHE_CONTROL).as_deref().and_then(parse_max_age);
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        state.next_fetch = next_fetch(max_age, None);
+        store_feed_state_to_db(db, rss_url, &state)?;
+        return Ok(None);
+    }
+
+    let bytes = response
+        .error_for_status()
+        .map_err(fetch_error)?
+        .bytes()
+        .await
+        .map_err(fetch_error)?;
+    let feed = parse_body(Some(db), rss_url, scrape, &bytes)?;
+
+    let state = FeedState {
+        etag,
+        last_modified,
+        next_fetch: next_fetch(max_age, feed.ttl),
+    };
+    Ok(Some((feed, state)))
+}
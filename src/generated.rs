@@ -0,0 +1,255 @@
+use crate::error::Error;
+use axum::{
+    extract::Path,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::{collections::HashSet, sync::Arc};
+use url::Url;
+
+/// Formats dates written out on pages are tried in, after RFC 3339 and RFC 2822.
+const DATE_FORMATS: [&str; 5] = ["%Y-%m-%d", "%B %d, %Y", "%b %d, %Y", "%d %B %Y", "%d %b %Y"];
+
+/// How to make a feed out of a page that has none, in the style of rsspls. The channel's
+/// `rss_url` is the page, and each item is found with CSS selectors.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct ScrapeSpec {
+    /// Name the feed is served under, at `/generated/{name}.xml`.
+    pub name: String,
+    /// Selects each item on the page, the other selectors are relative to it.
+    pub item: String,
+    pub title: String,
+    /// Selects the link to the item, its first link by default.
+    pub link: Option<String>,
+    /// Selects the date, from a `datetime` attribute or the text.
+    pub date: Option<String>,
+    pub summary: Option<String>,
+    pub image: Option<String>,
+}
+
+/// An item scraped from a page.
+struct Item {
+    title: String,
+    link: String,
+    published: Option<DateTime<Utc>>,
+    summary: Option<String>,
+    image: Option<String>,
+}
+
+fn selector(selector: &str) -> Result<Selector, Error> {
+    Selector::parse(selector)
+        .map_err(|e| Error::Validation(format!("Invalid selector '{selector}': {e}")))
+}
+
+/// Parse a date as written out on a page.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_rfc2822(date))
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            DATE_FORMATS.iter().find_map(|format| {
+                NaiveDate::parse_from_str(date, format)
+                    .ok()
+                    .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+            })
+        })
+}
+
+/// The first element under an element that a selector matches.
+fn first<'a>(element: &ElementRef<'a>, selector: &Selector) -> Option<ElementRef<'a>> {
+    element.select(selector).next()
+}
+
+/// Find the items on a page.
+fn scrape_items(page: &Url, spec: &ScrapeSpec, html: &str) -> Result<Vec<Item>, Error> {
+    let item = selector(&spec.item)?;
+    let title = selector(&spec.title)?;
+    let link = selector(spec.link.as_deref().unwrap_or("a"))?;
+    let date = spec.date.as_deref().map(selector).transpose()?;
+    let summary = spec.summary.as_deref().map(selector).transpose()?;
+    let image = spec.image.as_deref().map(selector).transpose()?;
+
+    let text = |element: ElementRef| {
+        let text = element.text().collect::<Vec<_>>().join(" ");
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+    let resolve = |link: &str| page.join(link.trim()).ok().map(String::from);
+
+    let document = Html::parse_document(html);
+    let mut seen = HashSet::new();
+    Ok(document
+        .select(&item)
+        .filter_map(|element| {
+            // The item itself can be the link
+            let link = first(&element, &link)
+                .or_else(|| element.value().attr("href").map(|_| element))?
+                .value()
+                .attr("href")
+                .and_then(resolve)?;
+            let title = first(&element, &title).map(text)?;
+            Some(Item {
+                published: date
+                    .as_ref()
+                    .and_then(|date| first(&element, date))
+                    .and_then(|date| {
+                        parse_date(date.value().attr("datetime").unwrap_or(&text(date)))
+                    }),
+                summary: summary
+                    .as_ref()
+                    .and_then(|summary| first(&element, summary))
+                    .map(|summary| summary.inner_html().trim().to_string()),
+                image: image
+                    .as_ref()
+                    .and_then(|image| first(&element, image))
+                    .and_then(|image| {
+                        let value = image.value();
+                        value.attr("src").or_else(|| value.attr("content"))
+                    })
+                    .and_then(resolve),
+                title,
+                link,
+            })
+        })
+        .filter(|item| !item.title.is_empty() && seen.insert(item.link.clone()))
+        .collect())
+}
+
+fn first_seen_prefix(page: &str) -> String {
+    format!("firstseen:{page}\0")
+}
+
+/// Date the undated items of a page by when they were first seen on it, so they keep their place
+/// across fetches. Items no longer on the page are forgotten.
+fn date_by_first_seen(db: &Db, page: &str, items: &mut [Item]) -> Result<(), Error> {
+    let prefix = first_seen_prefix(page);
+    let now = Utc::now();
+    for item in items.iter_mut().filter(|item| item.published.is_none()) {
+        let key = format!("{prefix}{}", item.link);
+        let first_seen = db.get(&key)?.and_then(|ivec| {
+            DateTime::parse_from_rfc3339(&String::from_utf8_lossy(&ivec))
+                .map(|date| date.with_timezone(&Utc))
+                .ok()
+        });
+        if first_seen.is_none() {
+            db.insert(&key, now.to_rfc3339().as_bytes())?;
+        }
+        item.published = Some(first_seen.unwrap_or(now));
+    }
+
+    let links: HashSet<&str> = items.iter().map(|item| item.link.as_str()).collect();
+    for key in db.scan_prefix(&prefix).keys() {
+        let key = key?;
+        if !links.contains(String::from_utf8_lossy(&key[prefix.len()..]).as_ref()) {
+            db.remove(key)?;
+        }
+    }
+    db.flush()?;
+    Ok(())
+}
+
+/// Function to forget when the items of a page were first seen.
+pub fn delete_first_seen_from_db(db: &Db, page: &str) -> Result<(), Error> {
+    for key in db.scan_prefix(first_seen_prefix(page)).keys() {
+        db.remove(key?)?;
+    }
+    Ok(())
+}
+
+/// Make an RSS feed out of a page, following its scrape spec. Items without a date are dated by
+/// when they were first seen, when there is a database to remember that in.
+pub fn render(db: Option<&Db>, page: &str, spec: &ScrapeSpec, html: &str) -> Result<String, Error> {
+    let page_url =
+        Url::parse(page).map_err(|e| Error::Feed(format!("Invalid link {page}: {e}")))?;
+    let mut items = scrape_items(&page_url, spec, html)?;
+    if let Some(db) = db {
+        date_by_first_seen(db, page, &mut items)?;
+    }
+    let title = Html::parse_document(html)
+        .select(&selector("title")?)
+        .next()
+        .map(|title| title.text().collect::<String>().trim().to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| spec.name.clone());
+
+    let mut body = String::new();
+    for item in items {
+        body.push_str("    <item>\n");
+        body.push_str(&format!(
+            "      <title>{}</title>\n",
+            encode_text(&item.title)
+        ));
+        body.push_str(&format!("      <link>{}</link>\n", encode_text(&item.link)));
+        body.push_str(&format!(
+            "      <guid isPermaLink=\"true\">{}</guid>\n",
+            encode_text(&item.link)
+        ));
+        if let Some(published) = item.published {
+            body.push_str(&format!(
+                "      <pubDate>{}</pubDate>\n",
+                published.to_rfc2822()
+            ));
+        }
+        if let Some(summary) = &item.summary {
+            body.push_str(&format!(
+                "      <description>{}</description>\n",
+                encode_text(summary)
+            ));
+        }
+        if let Some(image) = &item.image {
+            // Readers, and the article pipeline, take the first image of the content
+            let content = format!(
+                "<img src=\"{}\">{}",
+                encode_double_quoted_attribute(image),
+                item.summary.as_deref().unwrap_or_default()
+            );
+            body.push_str(&format!(
+                "      <content:encoded>{}</content:encoded>\n",
+                encode_text(&content)
+            ));
+        }
+        body.push_str("    </item>\n");
+    }
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\" xmlns:content=\"http://purl.org/rss/1.0/modules/content/\">\n  <channel>\n    <title>{title}</title>\n    <link>{link}</link>\n    <description>Generated from {link}</description>\n{body}  </channel>\n</rss>\n",
+        title = encode_text(&title),
+        link = encode_text(page),
+    ))
+}
+
+/// Serve the feed generated from a channel's page, named like `{name}.xml`
+pub async fn get_generated(Path(file): Path<String>, db: Arc<Db>) -> Result<Response, Error> {
+    let name = file
+        .strip_suffix(".xml")
+        .ok_or(Error::NotFound("Generated feed".to_string()))?;
+    let config = crate::config::load()?;
+    let (page, spec) = config
+        .rss
+        .into_iter()
+        .find_map(|channel| {
+            let spec = channel.scrape.filter(|spec| spec.name == name)?;
+            Some((channel.rss_url, spec))
+        })
+        .ok_or(Error::NotFound("Generated feed".to_string()))?;
+
+    let scrape_error = |e: reqwest::Error| Error::Scrape(format!("{page}: {e}"));
+    let html = reqwest::get(&page)
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(scrape_error)?
+        .text()
+        .await
+        .map_err(scrape_error)?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        render(Some(&db), &page, &spec, &html)?,
+    )
+        .into_response())
+}
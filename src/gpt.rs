@@ -1,100 +1,314 @@
-use crate::articles::Summary;
+use crate::{
+    articles::{Article, Summary},
+    error::Error,
+    summarizer::{split_sentences, Summarizer, PROMPT_VERSION},
+};
 use async_openai::{
     types::{ChatCompletionRequestMessageArgs, CreateChatCompletionRequestArgs, Role},
     Client,
 };
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sled::Db;
-use std::error::Error;
-use std::hash::{Hash, Hasher};
-use std::{collections::hash_map::DefaultHasher, sync::Arc};
-
-// Helper function to compute the hash of a string
-fn compute_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+use std::{collections::HashSet, sync::Arc};
+
+/// Version of the summary key scheme, the start of every key it produces.
+const SUMMARY_KEY_VERSION: &str = "v1";
+/// How long summaries of the chunks of long articles are kept for retries to reuse.
+const CHUNK_SUMMARY_MAX_AGE_DAYS: i64 = 7;
+
+/// Summary of a chunk of a long article, with when it was made so it can expire. No article
+/// refers to these, so they are kept under their own prefix.
+#[derive(Deserialize, Serialize, Debug)]
+struct ChunkSummary {
+    summary: String,
+    created: String,
+}
+
+// Helper function to compute a stable hash of some fields, each prefixed with its length so they
+// cannot run into each other
+fn compute_hash(fields: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for field in fields {
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Helper function to compute the hash of what a summariser is asked to summarise
+fn input_hash(summarizer: &dyn Summarizer, prompt: &str, title: &str, text: &str) -> String {
+    compute_hash(&[
+        &summarizer.model_id(),
+        &PROMPT_VERSION.to_string(),
+        prompt,
+        title,
+        text,
+    ])
+}
+
+/// Database key the summary of an article by a summariser with a prompt template is cached under
+pub fn summary_key(summarizer: &dyn Summarizer, prompt: &str, title: &str, text: &str) -> String {
+    let hash = input_hash(summarizer, prompt, title, text);
+    format!("summary:{SUMMARY_KEY_VERSION}:{hash}")
 }
 
+/// Database key the summary of a chunk of a long article is cached under
+fn chunk_summary_key(
+    summarizer: &dyn Summarizer,
+    prompt: &str,
+    title: &str,
+    chunk: &str,
+) -> String {
+    let hash = input_hash(summarizer, prompt, title, chunk);
+    format!("summarychunk:{SUMMARY_KEY_VERSION}:{hash}")
+}
+
+/// Whether a summary key is from before keys were versioned, when they were a `DefaultHasher`
+/// hash that could change with the Rust release.
+fn is_legacy_summary_key(key: &str) -> bool {
+    key.strip_prefix("summary:")
+        .is_some_and(|hash| !hash.is_empty() && hash.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Drop the summaries cached under legacy keys, once, returning how many were dropped. What was
+/// summarised was not stored with them, so they cannot be keyed by content and no lookup would
+/// ever find them again. The articles keep their summaries, only the cache entries go.
+pub fn drop_legacy_summaries(db: &Db) -> Result<usize, Error> {
+    if db.get("meta:summary_key_version")?.as_deref() == Some(SUMMARY_KEY_VERSION.as_bytes()) {
+        return Ok(0);
+    }
+
+    let mut dropped = 0;
+    for key in db.scan_prefix("summary:").keys() {
+        let key = key?;
+        if is_legacy_summary_key(&String::from_utf8_lossy(&key)) {
+            db.remove(key)?;
+            dropped += 1;
+        }
+    }
+
+    // Unlink the articles from them
+    for entry in db.scan_prefix("article:") {
+        let (key, value) = entry?;
+        let Ok(mut article) = serde_json::from_slice::<Article>(&value) else {
+            continue;
+        };
+        if article
+            .summary_key
+            .as_deref()
+            .is_some_and(is_legacy_summary_key)
+        {
+            article.summary_key = None;
+            db.insert(key, serde_json::to_vec(&article)?)?;
+        }
+    }
+
+    db.insert("meta:summary_key_version", SUMMARY_KEY_VERSION)?;
+    db.flush()?;
+    Ok(dropped)
+}
+
+/// Remove cached summaries that no stored article refers to, returning how many were removed
+pub fn purge_orphaned_summaries(db: &Db, referenced: &HashSet<String>) -> Result<usize, Error> {
+    let mut purged = 0;
+    for key in db.scan_prefix("summary:").keys() {
+        let key = key?;
+        if !referenced.contains(String::from_utf8_lossy(&key).as_ref()) {
+            db.remove(key)?;
+            purged += 1;
+        }
+    }
+    db.flush()?;
+    Ok(purged)
+}
+
+/// Remove cached chunk summaries older than their maximum age, returning how many were removed
+pub fn purge_expired_chunk_summaries(db: &Db) -> Result<usize, Error> {
+    let oldest = Utc::now() - Duration::days(CHUNK_SUMMARY_MAX_AGE_DAYS);
+    let mut purged = 0;
+    for entry in db.scan_prefix("summarychunk:") {
+        let (key, value) = entry?;
+        let created = serde_json::from_slice::<ChunkSummary>(&value)
+            .ok()
+            .and_then(|chunk| DateTime::parse_from_rfc3339(&chunk.created).ok());
+        if created.is_none_or(|created| created < oldest) {
+            db.remove(key)?;
+            purged += 1;
+        }
+    }
+    db.flush()?;
+    Ok(purged)
+}
+
+/// Split text into chunks of at most `max_tokens`, breaking between sentences where possible.
+fn split_into_chunks(text: &str, max_tokens: usize, count: &dyn Fn(&str) -> usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_tokens = 0;
+    for sentence in split_sentences(text) {
+        let mut sentence = sentence;
+        let mut tokens = count(sentence);
+        if !chunk.is_empty() && chunk_tokens + tokens > max_tokens {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_tokens = 0;
+        }
+        // A single sentence longer than a chunk has to be cut, between words where possible,
+        // aiming a little short as tokens are not spread evenly
+        while tokens > max_tokens {
+            let mut cut = sentence.len() * max_tokens / tokens * 9 / 10;
+            while !sentence.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            if let Some(space) = sentence[..cut].rfind(char::is_whitespace) {
+                if space > 0 {
+                    cut = space;
+                }
+            }
+            if cut == 0 {
+                break;
+            }
+            chunks.push(sentence[..cut].trim().to_string());
+            sentence = sentence[cut..].trim_start();
+            tokens = count(sentence);
+        }
+        if !chunk.is_empty() {
+            chunk.push(' ');
+        }
+        chunk.push_str(sentence);
+        chunk_tokens += tokens;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Refuse to use a summariser whose monthly budget has been spent.
+fn check_budget(db: &Db, summarizer: &dyn Summarizer) -> Result<(), Error> {
+    if let Some(budget) = summarizer.monthly_budget() {
+        let spent = crate::usage::month_cost(db);
+        if spent >= budget {
+            return Err(Error::Llm(format!(
+                "Monthly budget of ${budget:.2} reached, ${spent:.2} spent"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Summarise with the summariser, recording what it used against the channel.
+async fn summarise_recorded(
+    db: &Db,
+    summarizer: &dyn Summarizer,
+    channel: &str,
+    prompt: &str,
+    title: &str,
+    text: &str,
+) -> Result<Summary, Error> {
+    check_budget(db, summarizer)?;
+    let mut usage = Vec::new();
+    let result = summarizer.summarise(prompt, title, text, &mut usage).await;
+    if let Err(e) = crate::usage::record(db, channel, &usage) {
+        eprintln!("Error recording usage: {e}");
+    }
+    result
+}
+
+/// Summarise a piece of a long article, cached on its own so a retry does not repeat it.
+async fn summarise_chunk(
+    db: &Db,
+    summarizer: &dyn Summarizer,
+    channel: &str,
+    prompt: &str,
+    title: &str,
+    chunk: &str,
+    refresh: bool,
+) -> Result<String, Error> {
+    let key = chunk_summary_key(summarizer, prompt, title, chunk);
+    if !refresh {
+        if let Some(ivec) = db.get(&key)? {
+            let cached: ChunkSummary = serde_json::from_slice(&ivec)?;
+            return Ok(cached.summary);
+        }
+    }
+
+    let summary = summarise_recorded(db, summarizer, channel, prompt, title, chunk).await?;
+    if summarizer.cacheable() {
+        let cached = ChunkSummary {
+            summary: summary.summary.clone(),
+            created: Utc::now().to_rfc3339(),
+        };
+        db.insert(key, serde_json::to_vec(&cached)?)?;
+        db.flush()?;
+    }
+    Ok(summary.summary)
+}
+
+/// Summarise an article, reusing the cached summary unless asked to `refresh` it.
 pub async fn summarise_article(
     db: Arc<Db>,
+    summarizer: &dyn Summarizer,
+    channel: &str,
+    prompt: &str,
     title: String,
     text: String,
-) -> Result<Summary, Box<dyn Error>> {
+    refresh: bool,
+) -> Result<Summary, Error> {
     // Attempt to retrieve the summary from the database, return that if found
-    let key = format!("summary:{}", compute_hash(&format!("{title}{text}")));
-    if db.contains_key(key.clone())? {
-        let ivec = db.get(key.clone())?.unwrap();
-        let summary: Summary = serde_json::from_slice(&ivec)?;
-        return Ok(summary);
+    let key = summary_key(summarizer, prompt, &title, &text);
+    if summarizer.cacheable() && !refresh {
+        if let Some(ivec) = db.get(&key)? {
+            let summary: Summary = serde_json::from_slice(&ivec)?;
+            return Ok(summary);
+        }
     }
 
-    // If the text is long it requires higher context model
-    let is_lengthy = text.len() > 4096 * 3;
-    let model = if is_lengthy {
-        "gpt-3.5-turbo-16k"
-    } else {
-        "gpt-3.5-turbo"
-    };
-
-    // If its still too long (over 30k characters), error out
-    if text.len() > 16384 * 3 {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Text too long",
-        )));
-    };
-
-    // Use GPT3.5 to summarise the article and title
-    let client = Client::new();
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(1024u16)
-        .model(model)
-        .messages([
-            ChatCompletionRequestMessageArgs::default()
-                .role(Role::User)
-                .content(format!("Provide a concise summary of the following content in JSON format. If it's an article, use the provided text. If it's a video, use the provided subtitles. The JSON should have keys 'title' (rephrased from the original for brevity) and 'summary' (condensed from the original content, maintaining the tone and style of the original). Ensure the summary includes all relevant context so that someone unfamiliar with the topic can understand.\nOriginal title: {title}\nOriginal content: {text}"))
-                .build()?,
-        ])
-        .build()?;
-    let response = client.chat().create(request).await?;
-    let result = response
-        .choices
-        .first()
-        .unwrap()
-        .message
-        .content
-        .clone()
-        .unwrap();
-
-    // Parse json, with error handling
-    let result: Summary = match serde_json::from_str(&result) {
-        Ok(summary) => {
-            println!("Successfully parsed GPT3.5 response for {title}");
-            summary
+    // Text too long to summarise in one go is summarised in chunks, then the chunk summaries are
+    // summarised together, repeating until they fit
+    let mut input = text;
+    if let Some(max_tokens) = summarizer.max_input_tokens(prompt) {
+        let count = |text: &str| summarizer.count_tokens(text);
+        if count(&input) > max_tokens {
+            input = crate::search::html_to_text(&input)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
         }
-        Err(e) => {
-            println!("Error parsing GPT3.5 response: {e}");
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Error parsing GPT3.5 response",
-            )));
+        while count(&input) > max_tokens {
+            let chunks = split_into_chunks(&input, max_tokens, &count);
+            println!("Summarising {title} in {} chunks", chunks.len());
+            let mut summaries = Vec::with_capacity(chunks.len());
+            for chunk in &chunks {
+                summaries.push(
+                    summarise_chunk(&db, summarizer, channel, prompt, &title, chunk, refresh)
+                        .await?,
+                );
+            }
+            let combined = summaries.join("\n\n");
+            if combined.len() >= input.len() {
+                return Err(Error::Llm(format!(
+                    "Summaries of {title} are not getting any shorter"
+                )));
+            }
+            input = combined;
         }
-    };
+    }
+
+    let result = summarise_recorded(&db, summarizer, channel, prompt, &title, &input).await?;
 
     // Store the summary in the database
-    let ivec = serde_json::to_vec(&result)?;
-    db.insert(key, ivec)?;
-    db.flush()?;
+    if summarizer.cacheable() {
+        let ivec = serde_json::to_vec(&result)?;
+        db.insert(key, ivec)?;
+        db.flush()?;
+    }
 
     Ok(result)
 }
 
-pub async fn process(
-    input: String,
-    model: &str,
-    max_tokens: u16,
-) -> Result<String, Box<dyn Error>> {
+pub async fn process(input: String, model: &str, max_tokens: u16) -> Result<String, Error> {
     // Use GPT3.5 to summarise the article and title
     let client = Client::new();
     let request = CreateChatCompletionRequestArgs::default()
@@ -106,14 +320,10 @@ pub async fn process(
             .build()?])
         .build()?;
     let response = client.chat().create(request).await?;
-    let result = response
+    response
         .choices
-        .first()
-        .unwrap()
-        .message
-        .content
-        .clone()
-        .unwrap();
-
-    Ok(result)
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .ok_or_else(|| Error::Llm(format!("Empty response from {model}")))
 }
@@ -0,0 +1,153 @@
+use crate::error::Error;
+use axum::response::Json;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sled::Db;
+use std::{sync::Arc, time::Instant};
+
+/// Failures in a row after which a channel is flagged as broken.
+const BROKEN_AFTER_FAILURES: u32 = 5;
+/// Weight of the latest pull in the average latency.
+const LATENCY_WEIGHT: f64 = 0.2;
+
+/// The last error a channel ran into.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct HealthError {
+    pub kind: String,
+    pub message: String,
+}
+
+/// How pulling a channel has been going.
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Debug)]
+pub struct ChannelHealth {
+    pub last_attempt: Option<String>,
+    pub last_success: Option<String>,
+    pub consecutive_failures: u32,
+    pub last_error: Option<HealthError>,
+    /// Status of the last response to a fetch of the feed.
+    pub http_status: Option<u16>,
+    /// Entries in the feed and how many of them became new articles, as of the last pull.
+    pub entries_seen: usize,
+    pub entries_new: usize,
+    /// Moving average of how long the feed's server takes to respond.
+    pub average_latency_ms: Option<f64>,
+    /// Whether the channel failed too often in a row to be expected to recover by itself.
+    pub broken: bool,
+}
+
+/// What happened during one pull of a channel, filled in as it goes.
+#[derive(Debug)]
+pub struct Pull {
+    started: Instant,
+    /// Whether the feed was actually requested, rather than skipped for not being due.
+    pub fetched: bool,
+    pub http_status: Option<u16>,
+    pub latency_ms: Option<f64>,
+    pub entries_seen: usize,
+    pub entries_new: usize,
+}
+
+impl Pull {
+    pub fn start() -> Self {
+        Pull {
+            started: Instant::now(),
+            fetched: false,
+            http_status: None,
+            latency_ms: None,
+            entries_seen: 0,
+            entries_new: 0,
+        }
+    }
+
+    /// Note the response to the feed request.
+    pub fn responded(&mut self, status: u16) {
+        self.http_status = Some(status);
+        self.latency_ms = Some(self.started.elapsed().as_secs_f64() * 1000.0);
+    }
+}
+
+/// Function to retrieve a channel's health from the database.
+pub fn get_health_from_db(db: &Db, rss_url: &str) -> Option<ChannelHealth> {
+    let ivec = db.get(format!("health:{rss_url}")).ok()??;
+    serde_json::from_slice(&ivec).ok()
+}
+
+/// Function to store a channel's health into the database.
+fn store_health_to_db(db: &Db, rss_url: &str, health: &ChannelHealth) -> Result<(), Error> {
+    db.insert(format!("health:{rss_url}"), serde_json::to_vec(health)?)?;
+    db.flush()?;
+    Ok(())
+}
+
+/// Function to remove a channel's health from the database.
+pub fn delete_health_from_db(db: &Db, rss_url: &str) -> Result<(), Error> {
+    db.remove(format!("health:{rss_url}"))?;
+    Ok(())
+}
+
+/// Failures in a row of a channel, to back off polling it.
+pub fn consecutive_failures(db: &Db, rss_url: &str) -> u32 {
+    get_health_from_db(db, rss_url).map_or(0, |health| health.consecutive_failures)
+}
+
+/// Record how a pull of a channel went, unless it never got as far as requesting the feed.
+pub fn record_pull(
+    db: &Db,
+    rss_url: &str,
+    pull: &Pull,
+    result: &Result<(), Error>,
+) -> Result<(), Error> {
+    if !pull.fetched {
+        return Ok(());
+    }
+
+    let mut health = get_health_from_db(db, rss_url).unwrap_or_default();
+    let now = Utc::now().to_rfc3339();
+    health.last_attempt = Some(now.clone());
+    health.http_status = pull.http_status;
+    if let Some(latency) = pull.latency_ms {
+        health.average_latency_ms = Some(health.average_latency_ms.map_or(latency, |average| {
+            average + (latency - average) * LATENCY_WEIGHT
+        }));
+    }
+    match result {
+        Ok(()) => {
+            health.last_success = Some(now);
+            health.consecutive_failures = 0;
+            health.entries_seen = pull.entries_seen;
+            health.entries_new = pull.entries_new;
+        }
+        Err(e) => {
+            health.consecutive_failures += 1;
+            health.last_error = Some(HealthError {
+                kind: e.kind().to_string(),
+                message: e.to_string(),
+            });
+            health.entries_seen = 0;
+            health.entries_new = 0;
+        }
+    }
+    health.broken = health.consecutive_failures >= BROKEN_AFTER_FAILURES;
+    store_health_to_db(db, rss_url, &health)
+}
+
+/// Get the health of every channel
+#[allow(clippy::unused_async)]
+pub async fn get_channels_health(db: Arc<Db>) -> Result<Json<serde_json::Value>, Error> {
+    let config = crate::config::load()?;
+
+    let channels: Vec<_> = config
+        .rss
+        .iter()
+        .map(|channel| {
+            json!({
+                "rss_url": channel.rss_url,
+                "title": channel.title,
+                "health": get_health_from_db(&db, &channel.rss_url),
+            })
+        })
+        .collect();
+
+    Ok(Json(json!(channels)))
+}
@@ -1,22 +1,36 @@
 mod articles;
 mod channel;
+mod config;
+mod content;
+mod dedupe;
+mod discover;
+mod error;
+mod extract;
+mod feed;
+mod generated;
 mod gpt;
+mod health;
+mod media;
+mod opml;
+mod retention;
+mod schedule;
+mod search;
+mod summarizer;
+mod tasks;
+mod usage;
 mod wallpaper;
 
 use axum::{
     extract::Path,
-    routing::{get, put},
-    Router,
+    routing::{get, patch, post, put},
+    Json, Router,
 };
+use error::Error;
+use extract::{JsonBody, QueryParams};
 use futures::{stream, StreamExt};
-use serde::{Deserialize, Serialize};
 use sled::Db;
-use std::{
-    fs::{read_to_string, write},
-    net::SocketAddr,
-    sync::Arc,
-};
-use tokio::time::{interval, Duration};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::time::Duration;
 use tower::ServiceBuilder;
 use tower_http::compression::CompressionLayer;
 use tower_http::services::ServeDir;
@@ -29,20 +43,131 @@ async fn main() {
     // Database setup
     let db = Arc::new(sled::open("database").expect("Failed to open database"));
 
+    // Build the article indexes if they are missing or outdated
+    match articles::rebuild_indexes(&db) {
+        Ok(true) => println!("Rebuilt article indexes"),
+        Ok(false) => {}
+        Err(e) => eprintln!("Error rebuilding article indexes: {e}"),
+    }
+
+    // Bring articles stored by an older version up to date
+    match articles::migrate_articles(&db) {
+        Ok(0) => {}
+        Ok(migrated) => println!("Migrated {migrated} articles"),
+        Err(e) => eprintln!("Error migrating articles: {e}"),
+    }
+
+    // Drop summaries cached under unstable legacy keys, nothing can look them up
+    match gpt::drop_legacy_summaries(&db) {
+        Ok(0) => {}
+        Ok(dropped) => println!("Dropped {dropped} summaries cached under legacy keys"),
+        Err(e) => eprintln!("Error dropping legacy cached summaries: {e}"),
+    }
+
+    // Make sure articles stored before search existed can be found
+    match search::index_missing_articles(&db) {
+        Ok(0) => {}
+        Ok(indexed) => println!("Indexed {indexed} articles for search"),
+        Err(e) => eprintln!("Error indexing articles for search: {e}"),
+    }
+
     // Create clones for the router
     let db_for_get = db.clone();
     let db_for_put = db.clone();
+    let db_for_resummarize = db.clone();
+    let db_for_content = db.clone();
+    let db_for_media = db.clone();
+    let db_for_stats = db.clone();
+    let db_for_schedule = db.clone();
+    let db_for_tasks = db.clone();
+    let db_for_search = db.clone();
+    let db_for_health = db.clone();
+    let db_for_generated = db.clone();
+    let db_for_add_channel = db.clone();
+    let db_for_edit_channel = db.clone();
+    let db_for_delete_channel = db.clone();
 
     // Router setup
     let app = Router::new()
         .nest_service("/", ServeDir::new("assets"))
-        .route("/articles", get(move || articles::get_articles(db_for_get)))
+        .route(
+            "/articles",
+            get(move |query: QueryParams<articles::ArticlesQuery>| {
+                articles::get_articles(query, db_for_get)
+            }),
+        )
+        .route(
+            "/articles/:link/content",
+            get(
+                move |path: Path<String>, query: QueryParams<content::ContentQuery>| {
+                    content::get_content(path, query, db_for_content)
+                },
+            ),
+        )
+        .route(
+            "/articles/:link/resummarize",
+            post(
+                move |path: Path<String>, request: Option<Json<articles::ResummarizeRequest>>| {
+                    articles::resummarize_article(path, db_for_resummarize, request)
+                },
+            ),
+        )
         .route(
             "/articles/:link/:new_status",
             put(move |path: Path<(String, String)>| {
                 articles::update_article_status(path, db_for_put)
             }),
         )
+        .route(
+            "/media/:hash",
+            get(move |path: Path<String>| media::get_media(path, db_for_media)),
+        )
+        .route(
+            "/search",
+            get(move |query: QueryParams<search::SearchQuery>| {
+                search::search_articles(query, db_for_search)
+            }),
+        )
+        .route(
+            "/stats/llm",
+            get(move |query: QueryParams<usage::StatsQuery>| {
+                usage::get_llm_stats(query, db_for_stats)
+            }),
+        )
+        .route("/opml", get(opml::export_opml).post(opml::import_opml))
+        .route("/discover", post(discover::discover_feeds))
+        .route(
+            "/generated/:file",
+            get(move |path: Path<String>| generated::get_generated(path, db_for_generated)),
+        )
+        .route(
+            "/schedule",
+            get(move || schedule::get_schedules(db_for_schedule)),
+        )
+        .route("/tasks", get(move || tasks::get_tasks(db_for_tasks)))
+        .route(
+            "/channels",
+            post(move |body: JsonBody<channel::ChannelOptional>| {
+                channel::add_channel(body, db_for_add_channel)
+            }),
+        )
+        .route(
+            "/channels/health",
+            get(move || health::get_channels_health(db_for_health)),
+        )
+        .route(
+            "/channels/:rss_url",
+            patch(
+                move |path: Path<String>, body: JsonBody<channel::ChannelPatch>| {
+                    channel::edit_channel(path, body, db_for_edit_channel)
+                },
+            )
+            .delete(
+                move |path: Path<String>, query: QueryParams<channel::DeleteOptions>| {
+                    channel::delete_channel(path, query, db_for_delete_channel)
+                },
+            ),
+        )
         .layer(ServiceBuilder::new().layer(CompressionLayer::new()));
 
     // Server setup
@@ -50,105 +175,116 @@ async fn main() {
     println!("Listening on {addr}");
     let server = axum::Server::bind(&addr).serve(app.into_make_service());
 
-    // Article puller setup
-    let article_puller = async {
-        let mut interval = interval(Duration::from_secs(20 * 60));
-        loop {
-            interval.tick().await;
-            println!("Pulling articles");
-            pull_articles(db.clone()).await;
-            println!("Done pulling articles");
-        }
-    };
+    // Article puller setup, checking every minute for channels that are due
+    let db_for_puller = db.clone();
+    tokio::spawn(tasks::supervise(
+        db.clone(),
+        "article_puller",
+        Duration::from_secs(60),
+        move || pull_articles(db_for_puller.clone()),
+    ));
+
+    // Retention enforcer setup
+    let db_for_retention = db.clone();
+    tokio::spawn(tasks::supervise(
+        db.clone(),
+        "retention_enforcer",
+        Duration::from_secs(60 * 60),
+        move || enforce_retention(db_for_retention.clone()),
+    ));
 
     // Wallpaper generator setup
-    let wallpaper_generator = async {
-        let mut interval = interval(Duration::from_secs(360 * 60));
-        loop {
-            interval.tick().await;
-            println!("Generating wallpaper");
-            // Attempt to generate a prompt for the wallpaper
-            match wallpaper::generate_prompt().await {
-                Ok(prompt) => {
-                    // Log the generated prompt
-                    println!("Prompt result: {prompt:?}");
-
-                    // Attempt to generate the wallpaper image using the prompt
-                    match wallpaper::generate_image(&prompt).await {
-                        Ok(wallpaper_result) => {
-                            // Log the successful generation of the wallpaper
-                            println!("Wallpaper result: {wallpaper_result:?}");
-                        }
-                        Err(e) => {
-                            // Log any errors encountered during wallpaper generation
-                            println!("Error generating wallpaper {e}");
-                        }
-                    }
-                }
-                Err(prompt_result) => {
-                    // Log any errors encountered during prompt generation
-                    println!("Error generating prompt {prompt_result:?}");
-                }
-            }
-        }
-    };
+    tokio::spawn(tasks::supervise(
+        db.clone(),
+        "wallpaper_generator",
+        Duration::from_secs(360 * 60),
+        generate_wallpaper,
+    ));
 
-    // Run server and article puller concurrently
-    tokio::select! {
-        _ = server => {
-            eprintln!("Server exited.");
-        }
-        _ = article_puller => {
-            eprintln!("Article puller exited.");
-        }
-        _ = wallpaper_generator => {
-            eprintln!("Wallpaper generator exited.");
-        }
+    // Run the server, the background tasks run alongside it until it exits
+    if let Err(e) = server.await {
+        eprintln!("Server exited: {e}");
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Config {
-    rss: Vec<channel::ChannelOptional>,
-}
-
-/// Get articles and write them to the database
-async fn pull_articles(db: Arc<Db>) {
-    let contents = read_to_string("feeds.toml").expect("Failed to read the file");
-    let mut config: Config = toml::from_str(&contents).expect("Failed to parse the TOML");
-    let mut config_changed = false;
+/// Get articles from the channels that are due and write them to the database
+async fn pull_articles(db: Arc<Db>) -> Result<(), Error> {
+    let config = config::load()?;
+    let due: Vec<_> = config
+        .rss
+        .iter()
+        .filter(|feed| schedule::is_due(&db, &feed.rss_url))
+        .collect();
+    if due.is_empty() {
+        return Ok(());
+    }
+    println!("Pulling articles from {} channels", due.len());
     let mut new_rss = Vec::new();
 
-    for feed in &config.rss {
+    for feed in &due {
         let needs_fresh =
             feed.title.is_none() || feed.icon.is_none() || feed.dominant_color.is_none();
 
         match channel::get_channel_data(&db, needs_fresh, feed).await {
             Ok(channel_data) => {
-                new_rss.push(channel_data);
-                config_changed = true;
+                if channel_data != **feed {
+                    new_rss.push(channel_data);
+                }
             }
             Err(e) => {
                 eprintln!("Error getting channel data for {}: {}", feed.rss_url, e);
-                new_rss.push(feed.clone());
             }
         }
     }
 
-    if config_changed {
-        config.rss = new_rss;
-        let toml = toml::to_string(&config).expect("Failed to serialize the TOML");
-        write("feeds.toml", toml).expect("Failed to write to file");
+    // Write back refreshed channel data, merging by url so concurrent edits are kept
+    if !new_rss.is_empty() {
+        config::update(|config| {
+            for channel_data in new_rss {
+                if let Some(feed) = config
+                    .rss
+                    .iter_mut()
+                    .find(|feed| feed.rss_url == channel_data.rss_url)
+                {
+                    *feed = channel_data;
+                }
+            }
+        })?;
     }
 
-    stream::iter(config.rss.iter())
+    stream::iter(due)
         .for_each_concurrent(2, |source| {
             let db = db.clone();
             async move {
-                if let Err(e) = articles::process_source(&source.rss_url, db).await {
+                if let Err(e) = articles::process_source(&source.rss_url, db.clone()).await {
                     eprintln!("Error processing source {}: {}", source.rss_url, e);
                 }
+                if let Err(e) = schedule::record_poll(&db, source) {
+                    eprintln!("Error scheduling source {}: {}", source.rss_url, e);
+                }
             }
         })
         .await;
+    println!("Done pulling articles");
+    Ok(())
+}
+
+/// Remove the articles, summaries and images the retention policy no longer keeps
+async fn enforce_retention(db: Arc<Db>) -> Result<(), Error> {
+    println!("Enforcing retention policy");
+    let rules = config::load()?.retention;
+    let (articles, summaries, media) = retention::enforce(&db, &rules)?;
+    println!("Removed {articles} articles, {summaries} summaries and {media} images");
+    Ok(())
+}
+
+/// Generate a wallpaper for the current weather and time of day
+async fn generate_wallpaper() -> Result<(), Error> {
+    println!("Generating wallpaper");
+    let api_key = wallpaper::image_api_key()?;
+    let prompt = wallpaper::generate_prompt().await?;
+    println!("Prompt result: {prompt:?}");
+    wallpaper::generate_image(&api_key, &prompt).await?;
+    println!("Generated wallpaper");
+    Ok(())
 }
@@ -0,0 +1,92 @@
+use crate::error::Error;
+use axum::{
+    extract::Path,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use image::ImageOutputFormat;
+use sha2::{Digest, Sha256};
+use sled::Db;
+use std::{collections::HashSet, io::Cursor, sync::Arc};
+
+/// Largest width and height of an article thumbnail.
+pub const THUMBNAIL_SIZE: u32 = 640;
+/// Largest width and height of a channel icon.
+pub const ICON_SIZE: u32 = 64;
+/// Media is stored under the hash of its original url, so it never changes.
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Hash identifying the media downloaded from a url, stable across Rust releases.
+fn media_hash(url: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(url.as_bytes()));
+    hash[..32].to_string()
+}
+
+/// Local path the media from a url is served from, if it has been cached.
+pub fn cached_path(db: &Db, url: &str) -> Option<String> {
+    let hash = media_hash(url);
+    db.contains_key(format!("media:{hash}"))
+        .ok()?
+        .then(|| format!("/media/{hash}"))
+}
+
+/// Download an image, shrink it to fit within `size` and store it as WebP, returning its local path.
+pub async fn cache_image(db: &Db, url: &str, size: u32) -> Result<String, Error> {
+    if let Some(path) = cached_path(db, url) {
+        return Ok(path);
+    }
+
+    let download_error = |e: reqwest::Error| Error::Scrape(format!("{url}: {e}"));
+    let bytes = reqwest::get(url)
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(download_error)?
+        .bytes()
+        .await
+        .map_err(download_error)?;
+    let mut img = image::load_from_memory(&bytes)
+        .map_err(|_| Error::Scrape(format!("Failed to decode the image from {url}")))?;
+    if img.width() > size || img.height() > size {
+        img = img.thumbnail(size, size);
+    }
+    let mut webp = Cursor::new(Vec::new());
+    img.to_rgba8()
+        .write_to(&mut webp, ImageOutputFormat::WebP)
+        .map_err(|e| Error::Scrape(format!("Failed to encode the image from {url}: {e}")))?;
+
+    let hash = media_hash(url);
+    db.insert(format!("media:{hash}"), webp.into_inner())?;
+    db.flush()?;
+    Ok(format!("/media/{hash}"))
+}
+
+/// Remove cached media that is not among the referenced local paths, returning how many were removed.
+pub fn purge_orphaned_media(db: &Db, referenced: &HashSet<String>) -> Result<usize, Error> {
+    let mut purged = 0;
+    for key in db.scan_prefix("media:").keys() {
+        let key = key?;
+        let hash = String::from_utf8_lossy(&key["media:".len()..]).to_string();
+        if !referenced.contains(&format!("/media/{hash}")) {
+            db.remove(key)?;
+            purged += 1;
+        }
+    }
+    db.flush()?;
+    Ok(purged)
+}
+
+/// Serve a cached image
+#[allow(clippy::unused_async)]
+pub async fn get_media(Path(hash): Path<String>, db: Arc<Db>) -> Result<Response, Error> {
+    let ivec = db
+        .get(format!("media:{hash}"))?
+        .ok_or(Error::NotFound("Media".to_string()))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/webp"),
+            (header::CACHE_CONTROL, CACHE_CONTROL),
+        ],
+        ivec.to_vec(),
+    )
+        .into_response())
+}
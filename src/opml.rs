@@ -0,0 +1,340 @@
+use crate::channel::ChannelOptional;
+use crate::error::Error;
+use axum::{
+    http::header,
+    response::{IntoResponse, Json, Response},
+};
+use html_escape::encode_double_quoted_attribute;
+use quick_xml::{events::Event, Reader};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Serialize channels to an OPML document, with categories as folder outlines.
+fn channels_to_opml(channels: &[ChannelOptional]) -> String {
+    // Group channels by category, channels without one go at the top level
+    let mut categories: BTreeMap<String, Vec<&ChannelOptional>> = BTreeMap::new();
+    for channel in channels {
+        let category = channel.category.clone().unwrap_or_default();
+        categories.entry(category).or_default().push(channel);
+    }
+
+    let outline = |channel: &ChannelOptional, indent: &str| {
+        let title = channel.title.as_deref().unwrap_or(&channel.rss_url);
+        format!(
+            "{indent}<outline type=\"rss\" text=\"{title}\" title=\"{title}\" xmlUrl=\"{url}\"/>\n",
+            title = encode_double_quoted_attribute(title),
+            url = encode_double_quoted_attribute(&channel.rss_url),
+        )
+    };
+
+    let mut body = String::new();
+    for (category, channels) in &categories {
+        if category.is_empty() {
+            for channel in channels {
+                body.push_str(&outline(channel, "    "));
+            }
+        } else {
+            let category = encode_double_quoted_attribute(category);
+            body.push_str(&format!(
+                "    <outline text=\"{category}\" title=\"{category}\">\n"
+            ));
+            for channel in channels {
+                body.push_str(&outline(channel, "      "));
+            }
+            body.push_str("    </outline>\n");
+        }
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n  <head>\n    <title>Rusty Reader subscriptions</title>\n  </head>\n  <body>\n{body}  </body>\n</opml>\n"
+    )
+}
+
+/// Parse the feeds out of an OPML document, using the enclosing folder outline as the category.
+fn opml_to_channels(opml: &str) -> Result<Vec<ChannelOptional>, quick_xml::Error> {
+    let mut reader = Reader::from_str(opml);
+    reader.trim_text(true);
+
+    let mut channels = Vec::new();
+    // Stack of open outlines, folders hold their name and feeds hold None
+    let mut folders: Vec<Option<String>> = Vec::new();
+
+    loop {
+        let (element, is_empty) = match reader.read_event()? {
+            Event::Start(element) => (element, false),
+            Event::Empty(element) => (element, true),
+            Event::End(element) => {
+                if element.name().as_ref() == b"outline" {
+                    folders.pop();
+                }
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        if element.name().as_ref() != b"outline" {
+            continue;
+        }
+
+        // Collect the attributes we care about
+        let mut text = None;
+        let mut title = None;
+        let mut xml_url = None;
+        let mut category = None;
+        for attribute in element.attributes() {
+            let attribute = attribute?;
+            let value = attribute
+                .decode_and_unescape_value(&reader)?
+                .trim()
+                .to_string();
+            if value.is_empty() {
+                continue;
+            }
+            match attribute.key.as_ref() {
+                b"text" => text = Some(value),
+                b"title" => title = Some(value),
+                b"xmlUrl" | b"xmlurl" => xml_url = Some(value),
+                b"category" => category = Some(value),
+                _ => {}
+            }
+        }
+
+        if let Some(rss_url) = xml_url {
+            // Prefer the nearest folder, then the outline's own category attribute
+            let folder = folders.iter().rev().find_map(Clone::clone);
+            let category = folder.or_else(|| {
+                category.and_then(|c| {
+                    c.split([',', '/'])
+                        .map(str::trim)
+                        .rfind(|c| !c.is_empty())
+                        .map(String::from)
+                })
+            });
+            channels.push(ChannelOptional {
+                category,
+                rss_url,
+                title: title.or(text),
+                icon: None,
+                dominant_color: None,
+                poll_interval: None,
+                prompt: None,
+                scrape: None,
+            });
+            if !is_empty {
+                folders.push(None);
+            }
+        } else if !is_empty {
+            folders.push(text.or(title));
+        }
+    }
+
+    Ok(channels)
+}
+
+/// Merge imported channels into the existing list keyed by `rss_url`, returning (added, updated).
+fn merge_channels(
+    existing: &mut Vec<ChannelOptional>,
+    imported: Vec<ChannelOptional>,
+) -> (usize, usize) {
+    let mut added = 0;
+    let mut updated = 0;
+    for channel in imported {
+        if let Some(current) = existing.iter_mut().find(|c| c.rss_url == channel.rss_url) {
+            // Only fill in what the existing entry is missing
+            let mut changed = false;
+            if current.category.is_none() && channel.category.is_some() {
+                current.category = channel.category;
+                changed = true;
+            }
+            if current.title.is_none() && channel.title.is_some() {
+                current.title = channel.title;
+                changed = true;
+            }
+            if changed {
+                updated += 1;
+            }
+        } else {
+            existing.push(channel);
+            added += 1;
+        }
+    }
+    (added, updated)
+}
+
+/// Export the subscribed channels as OPML
+#[allow(clippy::unused_async)]
+pub async fn export_opml() -> Result<Response, Error> {
+    let config = crate::config::load()?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/x-opml; charset=utf-8")],
+        channels_to_opml(&config.rss),
+    )
+        .into_response())
+}
+
+/// Import channels from an OPML document, merging them with the existing ones
+#[allow(clippy::unused_async)]
+pub async fn import_opml(body: String) -> Result<Json<serde_json::Value>, Error> {
+    let imported = opml_to_channels(&body).map_err(|e| Error::Parse(format!("OPML: {e}")))?;
+    let (added, updated) =
+        crate::config::update(|config| merge_channels(&mut config.rss, imported))?;
+    Ok(Json(json!({
+        "status": "success",
+        "message": format!("Imported {added} new and updated {updated} existing channels"),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(rss_url: &str, category: Option<&str>, title: Option<&str>) -> ChannelOptional {
+        ChannelOptional {
+            category: category.map(String::from),
+            rss_url: rss_url.to_string(),
+            title: title.map(String::from),
+            icon: None,
+            dominant_color: None,
+            poll_interval: None,
+            prompt: None,
+            scrape: None,
+        }
+    }
+
+    #[test]
+    fn opml_folders_become_categories() {
+        let opml = r#"<?xml version="1.0"?>
+            <opml version="2.0"><body>
+              <outline text="Top" xmlUrl="https://top.example/feed"/>
+              <outline text="Tech">
+                <outline text="Deeper">
+                  <outline title="Nested" xmlUrl="https://nested.example/feed"/>
+                </outline>
+                <outline text="Blog" xmlUrl="https://blog.example/feed"/>
+              </outline>
+            </body></opml>"#;
+        let channels = opml_to_channels(opml).unwrap();
+        assert_eq!(
+            channels,
+            [
+                channel("https://top.example/feed", None, Some("Top")),
+                channel(
+                    "https://nested.example/feed",
+                    Some("Deeper"),
+                    Some("Nested")
+                ),
+                channel("https://blog.example/feed", Some("Tech"), Some("Blog")),
+            ]
+        );
+    }
+
+    #[test]
+    fn opml_category_attribute_is_the_fallback() {
+        let opml = r#"<opml><body>
+              <outline text="A" category="/News/World/" xmlUrl="https://a.example/feed"/>
+              <outline text="Folder">
+                <outline text="B" category="Ignored" xmlUrl="https://b.example/feed"/>
+              </outline>
+            </body></opml>"#;
+        let channels = opml_to_channels(opml).unwrap();
+        assert_eq!(channels[0].category.as_deref(), Some("World"));
+        assert_eq!(channels[1].category.as_deref(), Some("Folder"));
+    }
+
+    #[test]
+    fn opml_feed_with_children_does_not_become_a_folder() {
+        let opml = r#"<opml><body>
+              <outline text="Feed" xmlUrl="https://feed.example/rss">
+                <outline text="Child" xmlUrl="https://child.example/rss"/>
+              </outline>
+              <outline text="After" xmlUrl="https://after.example/rss"/>
+            </body></opml>"#;
+        let channels = opml_to_channels(opml).unwrap();
+        assert_eq!(channels.len(), 3);
+        assert!(channels.iter().all(|channel| channel.category.is_none()));
+    }
+
+    #[test]
+    fn opml_skips_blank_attributes_and_unescapes() {
+        let opml = r#"<opml><body>
+              <outline text="Q &amp; A" title="  " xmlUrl=" https://qa.example/feed?a=1&amp;b=2 "/>
+              <outline text="No feed here" htmlUrl="https://site.example"/>
+            </body></opml>"#;
+        let channels = opml_to_channels(opml).unwrap();
+        assert_eq!(
+            channels,
+            [channel(
+                "https://qa.example/feed?a=1&b=2",
+                None,
+                Some("Q & A")
+            )]
+        );
+    }
+
+    #[test]
+    fn opml_rejects_malformed_documents() {
+        assert!(opml_to_channels("<opml><body><outline text=\"x></body></opml>").is_err());
+    }
+
+    #[test]
+    fn opml_export_round_trips() {
+        let channels = vec![
+            channel("https://a.example/feed", None, Some("A \"quoted\" <title>")),
+            channel(
+                "https://b.example/feed?x=1&y=2",
+                Some("News & Views"),
+                Some("B"),
+            ),
+            channel("https://c.example/feed", Some("News & Views"), None),
+        ];
+        let parsed = opml_to_channels(&channels_to_opml(&channels)).unwrap();
+        assert_eq!(
+            parsed,
+            [
+                channels[0].clone(),
+                channels[1].clone(),
+                channel(
+                    "https://c.example/feed",
+                    Some("News & Views"),
+                    Some("https://c.example/feed")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_adds_new_channels_and_fills_in_missing_fields() {
+        let mut existing = vec![
+            channel("https://a.example/feed", None, Some("Mine")),
+            channel("https://b.example/feed", Some("Kept"), Some("B")),
+        ];
+        let imported = vec![
+            channel("https://a.example/feed", Some("Imported"), Some("Theirs")),
+            channel("https://b.example/feed", Some("Other"), Some("Other")),
+            channel("https://c.example/feed", Some("New"), Some("C")),
+        ];
+        assert_eq!(merge_channels(&mut existing, imported), (1, 1));
+        assert_eq!(
+            existing,
+            [
+                channel("https://a.example/feed", Some("Imported"), Some("Mine")),
+                channel("https://b.example/feed", Some("Kept"), Some("B")),
+                channel("https://c.example/feed", Some("New"), Some("C")),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_collapses_duplicates_within_an_import() {
+        let mut existing = Vec::new();
+        let imported = vec![
+            channel("https://a.example/feed", None, Some("A")),
+            channel("https://a.example/feed", Some("Later"), Some("Again")),
+        ];
+        assert_eq!(merge_channels(&mut existing, imported), (1, 1));
+        assert_eq!(
+            existing,
+            [channel("https://a.example/feed", Some("Later"), Some("A"))]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    articles::{Article, ReadStatus},
+    channel::Channel,
+    error::Error,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::collections::{HashMap, HashSet};
+
+/// A rule for how long articles are kept.
+///
+/// Without a status the rule applies to Fresh and Archived articles, Saved articles are only
+/// ever removed by rules that name the Saved status explicitly.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct RetentionRule {
+    pub status: Option<ReadStatus>,
+    pub category: Option<String>,
+    pub max_age_days: Option<u32>,
+    pub max_count: Option<usize>,
+}
+
+impl RetentionRule {
+    /// Whether the rule applies to an article in the given category.
+    fn matches(&self, article: &Article, category: &str) -> bool {
+        let status_matches = match &self.status {
+            Some(status) => *status == article.read_status,
+            None => article.read_status != ReadStatus::Saved,
+        };
+        status_matches && self.category.as_ref().is_none_or(|c| c == category)
+    }
+}
+
+/// Remove articles that break any retention rule, along with summaries and images no longer
+/// referenced and expired chunk summaries. Returns the number of articles, summaries and images
+/// removed.
+pub fn enforce(db: &Db, rules: &[RetentionRule]) -> Result<(usize, usize, usize), Error> {
+    let now = Utc::now();
+    let articles: Vec<Article> = db
+        .scan_prefix("article:")
+        .filter_map(Result::ok)
+        .filter_map(|(_, value)| serde_json::from_slice(&value).ok())
+        .collect();
+
+    // Look up each channel's category once
+    let mut categories: HashMap<&str, String> = HashMap::new();
+    for article in &articles {
+        categories.entry(&article.channel).or_insert_with(|| {
+            crate::channel::get_channel_from_db(db, &article.channel)
+                .map(|channel| channel.category)
+                .unwrap_or_default()
+        });
+    }
+
+    let mut expired: HashSet<&str> = HashSet::new();
+    for rule in rules {
+        // Newest first, so the count limit keeps the most recent articles
+        let mut matching: Vec<(Option<DateTime<Utc>>, &Article)> = articles
+            .iter()
+            .filter(|article| rule.matches(article, &categories[article.channel.as_str()]))
+            .map(|article| {
+                let published = DateTime::parse_from_rfc3339(&article.published)
+                    .ok()
+                    .map(|published| published.with_timezone(&Utc));
+                (published, article)
+            })
+            .collect();
+        matching.sort_by_key(|(published, _)| std::cmp::Reverse(*published));
+
+        for (index, (published, article)) in matching.into_iter().enumerate() {
+            let too_old = match (rule.max_age_days, published) {
+                (Some(days), Some(published)) => now - published > Duration::days(days.into()),
+                _ => false,
+            };
+            let too_many = rule.max_count.is_some_and(|max_count| index >= max_count);
+            if too_old || too_many {
+                expired.insert(&article.link);
+            }
+        }
+    }
+
+    for link in &expired {
+        crate::articles::delete_article_from_db(db, link)?;
+    }
+    db.flush()?;
+
+    // Clear out summaries that only the removed (or no) articles referred to, reading the
+    // articles afresh so summaries stored while this ran are kept
+    let referenced: HashSet<String> = db
+        .scan_prefix("article:")
+        .filter_map(Result::ok)
+        .filter_map(|(_, value)| serde_json::from_slice::<Article>(&value).ok())
+        .flat_map(|article| {
+            let previous = article
+                .previous_summaries
+                .into_iter()
+                .filter_map(|version| version.summary_key);
+            article.summary_key.into_iter().chain(previous)
+        })
+        .collect();
+    let purged_summaries = crate::gpt::purge_orphaned_summaries(db, &referenced)?
+        + crate::gpt::purge_expired_chunk_summaries(db)?;
+
+    // Clear out cached images nothing refers to, reading the articles and channels afresh so
+    // images cached while this ran are kept
+    let mut referenced: HashSet<String> = db
+        .scan_prefix("article:")
+        .filter_map(Result::ok)
+        .filter_map(|(_, value)| serde_json::from_slice::<Article>(&value).ok())
+        .filter_map(|article| article.thumbnail)
+        .collect();
+    referenced.extend(
+        db.scan_prefix("channel:")
+            .filter_map(Result::ok)
+            .filter_map(|(_, value)| serde_json::from_slice::<Channel>(&value).ok())
+            .filter_map(|channel| channel.icon_thumbnail),
+    );
+    let purged_media = crate::media::purge_orphaned_media(db, &referenced)?;
+
+    Ok((expired.len(), purged_summaries, purged_media))
+}
@@ -0,0 +1,153 @@
+use crate::channel::ChannelOptional;
+use crate::error::Error;
+use axum::response::Json;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sled::Db;
+use std::sync::Arc;
+
+/// Polling interval used until a channel has enough history to adapt to.
+const DEFAULT_INTERVAL_MINUTES: i64 = 20;
+/// Bounds of the adaptive polling interval.
+const MIN_INTERVAL_MINUTES: i64 = 10;
+const MAX_INTERVAL_MINUTES: i64 = 12 * 60;
+/// Longest polling interval a channel can be set to, for feeds that only change now and then.
+const MAX_POLL_INTERVAL_MINUTES: u64 = 7 * 24 * 60;
+/// How many recent articles the posting frequency is worked out from.
+const HISTORY_LENGTH: usize = 20;
+/// How many polls to aim for between two posts.
+const POLLS_PER_POST: i32 = 4;
+/// Most times the interval of a failing channel is doubled.
+const MAX_BACKOFF_DOUBLINGS: u32 = 8;
+
+/// When a channel was last polled and when it is next due.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct Schedule {
+    pub interval_minutes: i64,
+    pub overridden: bool,
+    pub last_poll: String,
+    pub next_due: String,
+}
+
+/// Function to retrieve a channel's schedule from the database.
+fn get_schedule_from_db(db: &Db, rss_url: &str) -> Result<Option<Schedule>, Error> {
+    match db.get(format!("schedule:{rss_url}"))? {
+        Some(ivec) => Ok(Some(serde_json::from_slice(&ivec)?)),
+        None => Ok(None),
+    }
+}
+
+/// Function to store a channel's schedule into the database.
+fn store_schedule_to_db(db: &Db, rss_url: &str, schedule: &Schedule) -> Result<(), Error> {
+    let ivec = serde_json::to_vec(schedule)?;
+    db.insert(format!("schedule:{rss_url}"), ivec)?;
+    db.flush()?;
+    Ok(())
+}
+
+/// Work out how often to poll a channel from how often it has posted recently.
+fn adaptive_interval(db: &Db, rss_url: &str) -> Duration {
+    let published: Vec<DateTime<Utc>> = crate::articles::channel_articles(db, rss_url)
+        .into_iter()
+        // Entries without a date are stored at the epoch and say nothing about frequency
+        .filter(|(timestamp, _)| *timestamp > 0)
+        .filter_map(|(timestamp, _)| Utc.timestamp_millis_opt(timestamp).single())
+        .take(HISTORY_LENGTH)
+        .collect();
+
+    if published.len() < 2 {
+        return Duration::minutes(DEFAULT_INTERVAL_MINUTES);
+    }
+
+    // Average gap between posts, measured up to now rather than the latest post so dormant
+    // channels slow down even if they used to be busy
+    let oldest = published[published.len() - 1];
+    let gaps = i32::try_from(published.len()).unwrap_or(i32::MAX);
+    let average_gap = (Utc::now() - oldest) / gaps;
+    (average_gap / POLLS_PER_POST).clamp(
+        Duration::minutes(MIN_INTERVAL_MINUTES),
+        Duration::minutes(MAX_INTERVAL_MINUTES),
+    )
+}
+
+/// Whether a channel is due to be polled.
+pub fn is_due(db: &Db, rss_url: &str) -> bool {
+    match get_schedule_from_db(db, rss_url) {
+        Ok(Some(schedule)) => DateTime::parse_from_rfc3339(&schedule.next_due)
+            .map_or(true, |next_due| Utc::now() >= next_due),
+        _ => true,
+    }
+}
+
+/// Stretch a polling interval for a channel that keeps failing, doubling it with every failure
+/// in a row up to the longest interval. Intervals set longer than that are never shortened.
+fn back_off(interval: Duration, failures: u32) -> Duration {
+    if failures == 0 {
+        return interval;
+    }
+    let factor = 2i32.saturating_pow(failures.min(MAX_BACKOFF_DOUBLINGS));
+    (interval * factor).min(interval.max(Duration::minutes(MAX_INTERVAL_MINUTES)))
+}
+
+/// Check that a polling interval set for a channel is within bounds.
+pub fn validate_poll_interval(poll_interval: Option<u64>) -> Result<(), Error> {
+    let bounds = MIN_INTERVAL_MINUTES.unsigned_abs()..=MAX_POLL_INTERVAL_MINUTES;
+    match poll_interval {
+        Some(minutes) if !bounds.contains(&minutes) => Err(Error::Validation(format!(
+            "Poll interval must be between {} and {} minutes",
+            bounds.start(),
+            bounds.end()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Record that a channel was just polled and schedule its next poll.
+pub fn record_poll(db: &Db, channel: &ChannelOptional) -> Result<(), Error> {
+    let (interval, overridden) = match channel.poll_interval {
+        // Intervals from the config file are not checked, so they are brought within bounds
+        Some(minutes) => {
+            let minutes = minutes.clamp(
+                MIN_INTERVAL_MINUTES.unsigned_abs(),
+                MAX_POLL_INTERVAL_MINUTES,
+            );
+            (
+                Duration::minutes(i64::try_from(minutes).unwrap_or(MAX_INTERVAL_MINUTES)),
+                true,
+            )
+        }
+        None => (adaptive_interval(db, &channel.rss_url), false),
+    };
+    let failures = crate::health::consecutive_failures(db, &channel.rss_url);
+    let interval = back_off(interval, failures);
+    let now = Utc::now();
+    let schedule = Schedule {
+        interval_minutes: interval.num_minutes(),
+        overridden,
+        last_poll: now.to_rfc3339(),
+        next_due: (now + interval).to_rfc3339(),
+    };
+    store_schedule_to_db(db, &channel.rss_url, &schedule)
+}
+
+/// Get the polling schedule of every channel
+#[allow(clippy::unused_async)]
+pub async fn get_schedules(db: Arc<Db>) -> Result<Json<serde_json::Value>, Error> {
+    let config = crate::config::load()?;
+
+    let schedules: Vec<_> = config
+        .rss
+        .iter()
+        .map(|channel| {
+            let schedule = get_schedule_from_db(&db, &channel.rss_url).ok().flatten();
+            json!({
+                "rss_url": channel.rss_url,
+                "title": channel.title,
+                "schedule": schedule,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!(schedules)))
+}
@@ -0,0 +1,359 @@
+use crate::articles::{Article, FullArticle, ReadStatus};
+use crate::{error::Error, extract::QueryParams};
+use axum::response::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sled::Db;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// BM25 ranking parameters.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+/// How much more a match in the title counts than one in the body.
+const TITLE_BOOST: f64 = 2.0;
+/// Longest token that gets indexed, longer ones are usually junk like base64.
+const MAX_TOKEN_LENGTH: usize = 40;
+/// Default and maximum number of results.
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// Per article index metadata, positions below `title_length` are in the title.
+#[derive(Deserialize, Serialize, Default, Debug)]
+struct IndexedDocument {
+    length: u32,
+    title_length: u32,
+    terms: Vec<String>,
+}
+
+/// Totals across the whole index, used for the average document length.
+#[derive(Deserialize, Serialize, Default, Debug)]
+struct IndexStats {
+    documents: u64,
+    total_length: u64,
+}
+
+/// Split text into lowercase alphanumeric tokens.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty() && token.chars().count() <= MAX_TOKEN_LENGTH)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Strip the markup from a HTML fragment, leaving its text.
+pub fn html_to_text(html: &str) -> String {
+    scraper::Html::parse_fragment(html)
+        .root_element()
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn posting_key(term: &str, link: &str) -> String {
+    format!("search:{term}\0{link}")
+}
+
+/// Adjust the index totals by a document being added or removed.
+fn update_stats(db: &Db, length: u32, added: bool) -> Result<(), Error> {
+    db.update_and_fetch("searchstats", |old| {
+        let mut stats: IndexStats = old
+            .and_then(|old| serde_json::from_slice(old).ok())
+            .unwrap_or_default();
+        if added {
+            stats.documents += 1;
+            stats.total_length += u64::from(length);
+        } else {
+            stats.documents = stats.documents.saturating_sub(1);
+            stats.total_length = stats.total_length.saturating_sub(u64::from(length));
+        }
+        serde_json::to_vec(&stats).ok()
+    })?;
+    Ok(())
+}
+
+/// Remove an article from the search index.
+pub fn remove_article(db: &Db, link: &str) -> Result<(), Error> {
+    let Some(ivec) = db.remove(format!("searchdoc:{link}"))? else {
+        return Ok(());
+    };
+    let document: IndexedDocument = serde_json::from_slice(&ivec)?;
+    for term in &document.terms {
+        db.remove(posting_key(term, link))?;
+    }
+    update_stats(db, document.length, false)?;
+    Ok(())
+}
+
+/// Add an article to the search index, replacing any previous entry for it.
+pub fn index_article(
+    db: &Db,
+    article: &Article,
+    channel_title: &str,
+    body: &str,
+) -> Result<(), Error> {
+    remove_article(db, &article.link)?;
+
+    // Index the fields as one run of tokens, title first so title matches can be boosted
+    let too_long = |_: std::num::TryFromIntError| {
+        Error::Storage(format!("{} is too long to index", article.link))
+    };
+    let title = tokenize(&article.title);
+    let title_length = u32::try_from(title.len()).map_err(too_long)?;
+    let tokens: Vec<String> = title
+        .into_iter()
+        .chain(tokenize(&html_to_text(&article.summary)))
+        .chain(tokenize(channel_title))
+        .chain(tokenize(body))
+        .collect();
+
+    let mut positions: HashMap<&str, Vec<u32>> = HashMap::new();
+    for (position, token) in tokens.iter().enumerate() {
+        positions
+            .entry(token)
+            .or_default()
+            .push(u32::try_from(position).map_err(too_long)?);
+    }
+    for (term, positions) in &positions {
+        db.insert(
+            posting_key(term, &article.link),
+            serde_json::to_vec(positions)?,
+        )?;
+    }
+
+    let document = IndexedDocument {
+        length: u32::try_from(tokens.len()).map_err(too_long)?,
+        title_length,
+        terms: positions.keys().map(|term| (*term).to_string()).collect(),
+    };
+    db.insert(
+        format!("searchdoc:{}", article.link),
+        serde_json::to_vec(&document)?,
+    )?;
+    update_stats(db, document.length, true)?;
+    db.flush()?;
+    Ok(())
+}
+
+/// Index any stored articles that are missing from the search index, without their body text.
+pub fn index_missing_articles(db: &Db) -> Result<usize, Error> {
+    let mut indexed = 0;
+    for (_, value) in db.scan_prefix("article:").filter_map(Result::ok) {
+        let Ok(article) = serde_json::from_slice::<Article>(&value) else {
+            continue;
+        };
+        if db.contains_key(format!("searchdoc:{}", article.link))? {
+            continue;
+        }
+        let channel_title = crate::channel::get_channel_from_db(db, &article.channel)
+            .map(|channel| channel.title)
+            .unwrap_or_default();
+        index_article(db, &article, &channel_title, "")?;
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+/// A parsed search query, phrases must appear in order and every term must appear.
+#[derive(Debug, Default)]
+struct ParsedQuery {
+    terms: Vec<String>,
+    phrases: Vec<Vec<String>>,
+}
+
+fn parse_query(query: &str) -> ParsedQuery {
+    let mut parsed = ParsedQuery::default();
+    // Every odd part sits between quotes
+    for (index, part) in query.split('"').enumerate() {
+        let tokens = tokenize(part);
+        if index % 2 == 1 && tokens.len() > 1 {
+            parsed.phrases.push(tokens.clone());
+        }
+        parsed.terms.extend(tokens);
+    }
+    parsed.terms.sort();
+    parsed.terms.dedup();
+    parsed
+}
+
+/// Get the positions of a term for every article containing it.
+fn postings(db: &Db, term: &str) -> HashMap<String, Vec<u32>> {
+    let prefix = format!("search:{term}\0");
+    db.scan_prefix(&prefix)
+        .filter_map(Result::ok)
+        .filter_map(|(key, value)| {
+            let link = String::from_utf8_lossy(&key[prefix.len()..]).to_string();
+            let positions = serde_json::from_slice(&value).ok()?;
+            Some((link, positions))
+        })
+        .collect()
+}
+
+/// Whether the phrase appears in consecutive positions.
+fn contains_phrase(
+    phrase: &[String],
+    positions: &HashMap<String, HashMap<String, Vec<u32>>>,
+    link: &str,
+) -> bool {
+    let term_positions: Option<Vec<HashSet<u32>>> = phrase
+        .iter()
+        .map(|term| {
+            positions
+                .get(term)
+                .and_then(|postings| postings.get(link))
+                .map(|positions| positions.iter().copied().collect())
+        })
+        .collect();
+    let Some(term_positions) = term_positions else {
+        return false;
+    };
+    term_positions[0].iter().any(|start| {
+        term_positions
+            .iter()
+            .enumerate()
+            .skip(1)
+            .all(|(offset, positions)| {
+                u32::try_from(offset).is_ok_and(|offset| positions.contains(&(start + offset)))
+            })
+    })
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    pub q: String,
+    pub status: Option<String>,
+    pub channel: Option<String>,
+    pub since: Option<String>,
+    pub before: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+struct SearchResult {
+    score: f64,
+    #[serde(flatten)]
+    article: FullArticle,
+}
+
+/// Search the articles, ranked by relevance
+#[allow(clippy::unused_async, clippy::cast_precision_loss)]
+pub async fn search_articles(
+    QueryParams(query): QueryParams<SearchQuery>,
+    db: Arc<Db>,
+) -> Result<Json<serde_json::Value>, Error> {
+    // Parse the filters
+    let status = query
+        .status
+        .as_deref()
+        .map(ReadStatus::from_str)
+        .transpose()
+        .map_err(Error::Validation)?;
+    let since = query
+        .since
+        .as_deref()
+        .map(crate::articles::parse_date)
+        .transpose()
+        .map_err(Error::Validation)?;
+    let before = query
+        .before
+        .as_deref()
+        .map(crate::articles::parse_date)
+        .transpose()
+        .map_err(Error::Validation)?;
+
+    let parsed = parse_query(&query.q);
+    if parsed.terms.is_empty() {
+        return Ok(Json(json!([])));
+    }
+
+    let stats: IndexStats = db
+        .get("searchstats")
+        .ok()
+        .flatten()
+        .and_then(|ivec| serde_json::from_slice(&ivec).ok())
+        .unwrap_or_default();
+    let average_length = stats.total_length as f64 / stats.documents.max(1) as f64;
+
+    // Candidates have to contain every term
+    let positions: HashMap<String, HashMap<String, Vec<u32>>> = parsed
+        .terms
+        .iter()
+        .map(|term| (term.clone(), postings(&db, term)))
+        .collect();
+    let mut candidates: Vec<&String> = positions[&parsed.terms[0]].keys().collect();
+    candidates.retain(|link| {
+        positions
+            .values()
+            .all(|postings| postings.contains_key(*link))
+            && parsed
+                .phrases
+                .iter()
+                .all(|phrase| contains_phrase(phrase, &positions, link))
+    });
+
+    let mut results: Vec<SearchResult> = candidates
+        .into_iter()
+        .filter_map(|link| {
+            let article = crate::articles::get_full_article_from_db(&db, link).ok()?;
+
+            // Apply the filters
+            if status
+                .as_ref()
+                .is_some_and(|status| *status != article.read_status)
+                || query
+                    .channel
+                    .as_ref()
+                    .is_some_and(|channel| *channel != article.channel.rss_url)
+            {
+                return None;
+            }
+            if since.is_some() || before.is_some() {
+                let published = crate::articles::parse_date(&article.published).ok()?;
+                if since.is_some_and(|since| published < since)
+                    || before.is_some_and(|before| published >= before)
+                {
+                    return None;
+                }
+            }
+
+            // Score with BM25, counting title matches extra
+            let document: IndexedDocument = db
+                .get(format!("searchdoc:{link}"))
+                .ok()
+                .flatten()
+                .and_then(|ivec| serde_json::from_slice(&ivec).ok())?;
+            let length_norm = 1.0 - B + B * f64::from(document.length) / average_length.max(1.0);
+            let score = parsed
+                .terms
+                .iter()
+                .map(|term| {
+                    let postings = &positions[term];
+                    let frequency: f64 = postings[link]
+                        .iter()
+                        .map(|position| {
+                            if *position < document.title_length {
+                                TITLE_BOOST
+                            } else {
+                                1.0
+                            }
+                        })
+                        .sum();
+                    let matching = postings.len() as f64;
+                    let idf =
+                        ((stats.documents as f64 - matching + 0.5) / (matching + 0.5)).ln_1p();
+                    idf * frequency * (K1 + 1.0) / (frequency + K1 * length_norm)
+                })
+                .sum();
+
+            Some(SearchResult { score, article })
+        })
+        .collect();
+
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results.truncate(query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT));
+
+    Ok(Json(json!(results)))
+}
@@ -0,0 +1,783 @@
+use crate::{articles::Summary, config::Config, error::Error, usage::Usage};
+use async_openai::{
+    config::OpenAIConfig,
+    error::OpenAIError,
+    types::{
+        ChatCompletionFunctionCall, ChatCompletionFunctions, ChatCompletionFunctionsArgs,
+        ChatCompletionRequestMessageArgs, CreateChatCompletionRequestArgs, Role,
+    },
+    Client,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tiktoken_rs::{model::get_context_size, tokenizer::Tokenizer};
+
+/// Models used when the config does not name any.
+const DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+const DEFAULT_LONG_MODEL: &str = "gpt-3.5-turbo-16k";
+/// How many times a model is asked again after giving a response that cannot be parsed.
+const MAX_PARSE_RETRIES: usize = 2;
+/// Name of the function used to return summaries through function calling.
+const SUMMARY_FUNCTION: &str = "save_summary";
+/// Tokens reserved for the model's response.
+const MAX_COMPLETION_TOKENS: u16 = 1024;
+/// Tokens left for the title when working out how much text fits in a request.
+const TITLE_TOKENS: usize = 100;
+/// Sentences kept by the extractive summariser when the config does not say.
+const DEFAULT_SENTENCES: usize = 3;
+/// Sentences considered by TextRank, which compares every pair of them.
+const MAX_SENTENCES: usize = 300;
+/// Longest a sentence can be before it is broken up.
+const MAX_SENTENCE_WORDS: usize = 40;
+/// TextRank damping factor and when to stop iterating.
+const DAMPING: f64 = 0.85;
+const TEXT_RANK_ITERATIONS: usize = 50;
+const CONVERGENCE: f64 = 1e-4;
+
+/// The prompt used when no template applies. `{title}` and `{content}` are filled in.
+pub const DEFAULT_PROMPT: &str = "Provide a concise summary of the following content in JSON format. If it's an article, use the provided text. If it's a video, use the provided subtitles. The JSON should have keys 'title' (rephrased from the original for brevity) and 'summary' (condensed from the original content, maintaining the tone and style of the original). Ensure the summary includes all relevant context so that someone unfamiliar with the topic can understand.\nOriginal title: {title}\nOriginal content: {content}";
+
+/// Version of the instructions wrapped around prompt templates, such as the retry feedback and the
+/// summary function. Bump it when they change so cached summaries are not reused.
+pub const PROMPT_VERSION: u32 = 1;
+
+/// A prompt from the `[prompts]` section of the config, used for channels in its categories or
+/// channels that name it.
+///
+/// Besides 'title' and 'summary' a template can ask for 'key_points' (a list of strings),
+/// 'tags' (a list of strings) and 'clickbait_score' (0 to 1 for how clickbait the original
+/// title is).
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct PromptTemplate {
+    pub template: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+}
+
+/// Fill the title and content into a prompt template.
+pub fn render_prompt(template: &str, title: &str, text: &str) -> String {
+    template
+        .replace("{title}", title)
+        .replace("{content}", text)
+}
+
+/// Pick the prompt template for a channel, the one it names, else one for its category.
+pub fn select_prompt(config: &Config, rss_url: &str) -> String {
+    let channel = config.rss.iter().find(|channel| channel.rss_url == rss_url);
+    let named = channel
+        .and_then(|channel| channel.prompt.as_ref())
+        .and_then(|name| config.prompts.get(name));
+    let by_category = || {
+        let category = channel.and_then(|channel| channel.category.as_ref())?;
+        config
+            .prompts
+            .values()
+            .find(|prompt| prompt.categories.contains(category))
+    };
+    named
+        .or_else(by_category)
+        .map_or(DEFAULT_PROMPT.to_string(), |prompt| prompt.template.clone())
+}
+
+/// Which summariser to use, from the `[summarizer]` section of the config.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum SummarizerConfig {
+    /// Any endpoint speaking the OpenAI chat completions API, such as llama.cpp or Ollama.
+    #[serde(rename = "openai")]
+    OpenAi {
+        api_base: Option<String>,
+        api_key: Option<String>,
+        model: Option<String>,
+        long_model: Option<String>,
+        /// Context sizes in tokens, known OpenAI models do not need them.
+        context_tokens: Option<usize>,
+        long_context_tokens: Option<usize>,
+        /// Ask for the summary through function calling, for endpoints that support it.
+        #[serde(default)]
+        function_calling: bool,
+        /// Estimated spend in a calendar month after which summarisation pauses.
+        monthly_budget: Option<f64>,
+        /// Prices per thousand tokens by model name, overriding the built in OpenAI prices.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        prices: HashMap<String, ModelPrice>,
+    },
+    /// Picks sentences out of the article itself, needs no API at all.
+    Extractive { sentences: Option<usize> },
+}
+
+/// Price of a model in dollars per thousand tokens.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Copy, Debug, Default)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// Price of the OpenAI models, anything else is assumed free.
+fn default_price(model: &str) -> ModelPrice {
+    let (prompt, completion) = if model.starts_with("gpt-4") {
+        (0.03, 0.06)
+    } else if model.starts_with("gpt-3.5-turbo-16k") {
+        (0.003, 0.004)
+    } else if model.starts_with("gpt-3.5-turbo") {
+        (0.0015, 0.002)
+    } else {
+        (0.0, 0.0)
+    };
+    ModelPrice { prompt, completion }
+}
+
+/// Count the tokens of a text with the tokenizer of a model, defaulting to `cl100k_base`.
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    let bpe = match tiktoken_rs::tokenizer::get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => tiktoken_rs::o200k_base_singleton(),
+        Some(Tokenizer::P50kBase) => tiktoken_rs::p50k_base_singleton(),
+        Some(Tokenizer::P50kEdit) => tiktoken_rs::p50k_edit_singleton(),
+        Some(Tokenizer::R50kBase | Tokenizer::Gpt2) => tiktoken_rs::r50k_base_singleton(),
+        Some(Tokenizer::Cl100kBase) | None => tiktoken_rs::cl100k_base_singleton(),
+    };
+    let tokens = bpe.lock().encode_with_special_tokens(text).len();
+    tokens
+}
+
+impl Default for SummarizerConfig {
+    fn default() -> Self {
+        SummarizerConfig::OpenAi {
+            api_base: None,
+            api_key: None,
+            model: None,
+            long_model: None,
+            context_tokens: None,
+            long_context_tokens: None,
+            function_calling: false,
+            monthly_budget: None,
+            prices: HashMap::new(),
+        }
+    }
+}
+
+/// Something that can condense an article into a short title and summary.
+#[async_trait]
+pub trait Summarizer: Send + Sync {
+    /// Summarise a text with a prompt template, adding any requests made to a model to `usage`,
+    /// even when failing.
+    async fn summarise(
+        &self,
+        prompt: &str,
+        title: &str,
+        text: &str,
+        usage: &mut Vec<Usage>,
+    ) -> Result<Summary, Error>;
+
+    /// Whether its summaries are worth caching, cheap ones are just recomputed.
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    /// Most tokens of text it can summarise in one go with a prompt template, longer ones are
+    /// summarised in chunks.
+    fn max_input_tokens(&self, _prompt: &str) -> Option<usize> {
+        None
+    }
+
+    /// Count the tokens of a text the way the summariser's model does.
+    fn count_tokens(&self, text: &str) -> usize {
+        count_tokens("", text)
+    }
+
+    /// Estimated spend in a calendar month after which it should not be used.
+    fn monthly_budget(&self) -> Option<f64> {
+        None
+    }
+
+    /// Models it summarises with, so changing them does not reuse cached summaries.
+    fn model_id(&self) -> String {
+        String::new()
+    }
+}
+
+/// Build the summariser described by the config.
+pub fn from_config(config: &SummarizerConfig) -> Arc<dyn Summarizer> {
+    match config.clone() {
+        SummarizerConfig::OpenAi {
+            api_base,
+            api_key,
+            model,
+            long_model,
+            context_tokens,
+            long_context_tokens,
+            function_calling,
+            monthly_budget,
+            prices,
+        } => {
+            // Without explicit settings the client falls back to OPENAI_API_KEY and the OpenAI API
+            let mut client_config = OpenAIConfig::new();
+            if let Some(api_base) = api_base {
+                client_config = client_config.with_api_base(api_base);
+            }
+            if let Some(api_key) = api_key {
+                client_config = client_config.with_api_key(api_key);
+            }
+            let model = model.unwrap_or(DEFAULT_MODEL.to_string());
+            let long_model = long_model.unwrap_or(DEFAULT_LONG_MODEL.to_string());
+            Arc::new(OpenAiSummarizer {
+                client: Client::with_config(client_config),
+                context_tokens: context_tokens.unwrap_or_else(|| get_context_size(&model)),
+                long_context_tokens: long_context_tokens
+                    .unwrap_or_else(|| get_context_size(&long_model)),
+                model,
+                long_model,
+                function_calling,
+                monthly_budget,
+                prices,
+            })
+        }
+        SummarizerConfig::Extractive { sentences } => Arc::new(ExtractiveSummarizer {
+            sentences: sentences.unwrap_or(DEFAULT_SENTENCES),
+        }),
+    }
+}
+
+/// Read the config file, using the defaults if it cannot be read.
+fn load_config() -> Config {
+    match crate::config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error reading config, using the default summariser: {e}");
+            Config::default()
+        }
+    }
+}
+
+/// Build the summariser and pick the prompt template for a channel from the config file, using
+/// the defaults if it cannot be read.
+pub fn load(rss_url: &str) -> (Arc<dyn Summarizer>, String) {
+    let config = load_config();
+    let summarizer = from_config(&config.summarizer.clone().unwrap_or_default());
+    (summarizer, select_prompt(&config, rss_url))
+}
+
+/// Like `load`, but with a different model and a prompt template picked by name.
+pub fn load_with(
+    rss_url: &str,
+    model: Option<&str>,
+    prompt: Option<&str>,
+) -> Result<(Arc<dyn Summarizer>, String), String> {
+    let config = load_config();
+    let mut summarizer_config = config.summarizer.clone().unwrap_or_default();
+    if let Some(new_model) = model {
+        match &mut summarizer_config {
+            SummarizerConfig::OpenAi {
+                model,
+                long_model,
+                context_tokens,
+                long_context_tokens,
+                ..
+            } => {
+                // The configured context sizes belong to the configured models
+                *model = Some(new_model.to_string());
+                *long_model = Some(new_model.to_string());
+                *context_tokens = None;
+                *long_context_tokens = None;
+            }
+            SummarizerConfig::Extractive { .. } => {
+                return Err("The extractive summariser does not use a model".to_string())
+            }
+        }
+    }
+    let prompt = match prompt {
+        Some(name) => config
+            .prompts
+            .get(name)
+            .map(|prompt| prompt.template.clone())
+            .ok_or(format!("There is no prompt named '{name}'"))?,
+        None => select_prompt(&config, rss_url),
+    };
+    Ok((from_config(&summarizer_config), prompt))
+}
+
+/// Function the model is asked to call with the summary when function calling is enabled.
+fn summary_function() -> Result<ChatCompletionFunctions, OpenAIError> {
+    ChatCompletionFunctionsArgs::default()
+        .name(SUMMARY_FUNCTION)
+        .description("Save the summary of the content")
+        .parameters(json!({
+            "type": "object",
+            "properties": {
+                "title": {"type": "string", "description": "The title rephrased for brevity"},
+                "summary": {"type": "string", "description": "The condensed content"},
+                "key_points": {"type": "array", "items": {"type": "string"}, "description": "The main points, if asked for"},
+                "tags": {"type": "array", "items": {"type": "string"}, "description": "Topics covered, if asked for"},
+                "clickbait_score": {"type": "number", "description": "From 0 to 1, how clickbait the original title is, if asked for"},
+            },
+            "required": ["title", "summary"],
+        }))
+        .build()
+}
+
+/// Summarises with a chat completion model behind an OpenAI compatible API.
+pub struct OpenAiSummarizer {
+    client: Client<OpenAIConfig>,
+    model: String,
+    long_model: String,
+    context_tokens: usize,
+    long_context_tokens: usize,
+    function_calling: bool,
+    monthly_budget: Option<f64>,
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl OpenAiSummarizer {
+    /// Estimated cost of a request to a model.
+    #[allow(clippy::cast_precision_loss)]
+    fn cost(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        let price = self
+            .prices
+            .get(model)
+            .copied()
+            .unwrap_or_else(|| default_price(model));
+        (prompt_tokens as f64 * price.prompt + completion_tokens as f64 * price.completion) / 1000.0
+    }
+}
+
+/// Cut the JSON object starting at a `{` out of a response, up to the `}` that closes it.
+fn object_at(response: &str, start: usize) -> Option<&str> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in response[start..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&response[start..=start + index]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The JSON objects that could be cut out of a response, skipping markdown fences and any prose
+/// around them. Prose can have braces of its own, so one is tried from every `{` in turn.
+fn json_objects(response: &str) -> impl Iterator<Item = &str> {
+    response
+        .match_indices('{')
+        .filter_map(move |(start, _)| object_at(response, start))
+}
+
+/// Fix the mistakes models commonly make in JSON, raw line breaks inside strings and trailing
+/// commas.
+fn repair_json(json: &str) -> String {
+    let mut repaired = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in json.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                '\n' => {
+                    repaired.push_str("\\n");
+                    continue;
+                }
+                '\r' => continue,
+                '\t' => {
+                    repaired.push_str("\\t");
+                    continue;
+                }
+                _ => {}
+            }
+        } else {
+            match c {
+                '"' => in_string = true,
+                '}' | ']' => {
+                    let trimmed = repaired.trim_end().len();
+                    if repaired[..trimmed].ends_with(',') {
+                        repaired.truncate(trimmed - 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        repaired.push(c);
+    }
+    repaired
+}
+
+/// Parse a summary out of a model response, tolerating fences, surrounding prose and small
+/// syntax errors.
+pub fn parse_summary(response: &str) -> Result<Summary, serde_json::Error> {
+    let error = match serde_json::from_str(response) {
+        Ok(summary) => return Ok(summary),
+        Err(e) => e,
+    };
+    json_objects(response)
+        .find_map(|object| {
+            serde_json::from_str(object)
+                .or_else(|_| serde_json::from_str(&repair_json(object)))
+                .ok()
+        })
+        .ok_or(error)
+}
+
+#[async_trait]
+impl Summarizer for OpenAiSummarizer {
+    async fn summarise(
+        &self,
+        prompt: &str,
+        title: &str,
+        text: &str,
+        usage: &mut Vec<Usage>,
+    ) -> Result<Summary, Error> {
+        let prompt = render_prompt(prompt, title, text);
+
+        // Use the cheaper model when the request and its response fit in its context
+        let fits = |model: &str, context: usize| {
+            count_tokens(model, &prompt) + usize::from(MAX_COMPLETION_TOKENS) <= context
+        };
+        let model = if fits(&self.model, self.context_tokens) {
+            &self.model
+        } else if fits(&self.long_model, self.long_context_tokens) {
+            &self.long_model
+        } else {
+            return Err(Error::Llm("Text too long".to_string()));
+        };
+
+        let mut messages = vec![ChatCompletionRequestMessageArgs::default()
+            .role(Role::User)
+            .content(prompt)
+            .build()?];
+
+        // Retry a few times, telling the model what was wrong with its last response
+        let mut attempt = 0;
+        loop {
+            let mut request = CreateChatCompletionRequestArgs::default();
+            request
+                .max_tokens(MAX_COMPLETION_TOKENS)
+                .model(model)
+                .messages(messages.clone());
+            if self.function_calling {
+                request.functions([summary_function()?]).function_call(
+                    ChatCompletionFunctionCall::Object(json!({"name": SUMMARY_FUNCTION})),
+                );
+            }
+            let response = self.client.chat().create(request.build()?).await?;
+            let message = response
+                .choices
+                .into_iter()
+                .next()
+                .map(|choice| choice.message);
+            let result = message
+                .and_then(|message| {
+                    message
+                        .function_call
+                        .map(|call| call.arguments)
+                        .or(message.content)
+                })
+                .unwrap_or_default();
+
+            // Servers that do not report usage get it counted locally
+            let (prompt_tokens, completion_tokens) = match response.usage {
+                Some(reported) => (
+                    u64::from(reported.prompt_tokens),
+                    u64::from(reported.completion_tokens),
+                ),
+                None => {
+                    let prompt_tokens: usize = messages
+                        .iter()
+                        .filter_map(|message| message.content.as_deref())
+                        .map(|content| count_tokens(model, content))
+                        .sum();
+                    (prompt_tokens as u64, count_tokens(model, &result) as u64)
+                }
+            };
+            usage.push(Usage {
+                model: model.clone(),
+                prompt_tokens,
+                completion_tokens,
+                cost: self.cost(model, prompt_tokens, completion_tokens),
+            });
+            if result.is_empty() {
+                return Err(Error::Llm(format!("Empty response from {model}")));
+            }
+
+            // Parse json, with error handling
+            match parse_summary(&result) {
+                Ok(summary) => {
+                    println!("Successfully parsed {model} response for {title}");
+                    return Ok(summary);
+                }
+                Err(e) if attempt == MAX_PARSE_RETRIES => {
+                    println!("Error parsing {model} response: {e}");
+                    return Err(Error::Llm(format!("Error parsing {model} response: {e}")));
+                }
+                Err(e) => {
+                    println!("Error parsing {model} response, retrying: {e}");
+                    attempt += 1;
+                    messages.push(
+                        ChatCompletionRequestMessageArgs::default()
+                            .role(Role::Assistant)
+                            .content(result)
+                            .build()?,
+                    );
+                    messages.push(
+                        ChatCompletionRequestMessageArgs::default()
+                            .role(Role::User)
+                            .content(format!("That could not be parsed as JSON ({e}). Reply with only the JSON object the first message asked for."))
+                            .build()?,
+                    );
+                }
+            }
+        }
+    }
+
+    fn max_input_tokens(&self, prompt: &str) -> Option<usize> {
+        let overhead = count_tokens(&self.long_model, &render_prompt(prompt, "", ""))
+            + TITLE_TOKENS
+            + usize::from(MAX_COMPLETION_TOKENS);
+        Some(self.long_context_tokens.saturating_sub(overhead))
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        count_tokens(&self.long_model, text)
+    }
+
+    fn monthly_budget(&self) -> Option<f64> {
+        self.monthly_budget
+    }
+
+    fn model_id(&self) -> String {
+        if self.model == self.long_model {
+            self.model.clone()
+        } else {
+            format!("{}+{}", self.model, self.long_model)
+        }
+    }
+}
+
+/// Summarises by picking out the most central sentences with TextRank, leaving the title as it
+/// is.
+pub struct ExtractiveSummarizer {
+    sentences: usize,
+}
+
+impl Default for ExtractiveSummarizer {
+    fn default() -> Self {
+        ExtractiveSummarizer {
+            sentences: DEFAULT_SENTENCES,
+        }
+    }
+}
+
+/// Split text into sentences at full stops, question and exclamation marks.
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        let end = index + c.len_utf8();
+        let at_boundary = text[end..].chars().next().is_none_or(char::is_whitespace);
+        if matches!(c, '.' | '!' | '?') && at_boundary {
+            sentences.push(text[start..end].trim());
+            start = end;
+        }
+    }
+    sentences.push(text[start..].trim());
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+/// Break up runs of text without punctuation, like auto generated subtitles, into pieces of
+/// at most `MAX_SENTENCE_WORDS` words.
+fn bound_sentence(sentence: &str) -> Vec<String> {
+    sentence
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .chunks(MAX_SENTENCE_WORDS)
+        .map(|words| words.join(" "))
+        .collect()
+}
+
+/// Score sentences with TextRank, PageRank over a graph weighted by how many words two
+/// sentences share, normalised by their lengths.
+#[allow(clippy::cast_precision_loss)]
+fn text_rank(sentences: &[String]) -> Vec<f64> {
+    let words: Vec<HashSet<String>> = sentences
+        .iter()
+        .map(|sentence| crate::search::tokenize(sentence).into_iter().collect())
+        .collect();
+    let count = sentences.len();
+
+    let mut weights = vec![vec![0.0; count]; count];
+    for i in 0..count {
+        for j in (i + 1)..count {
+            let shared = words[i].intersection(&words[j]).count();
+            let norm = (words[i].len() as f64).ln() + (words[j].len() as f64).ln();
+            if shared > 0 && norm > 0.0 {
+                weights[i][j] = shared as f64 / norm;
+                weights[j][i] = weights[i][j];
+            }
+        }
+    }
+    let totals: Vec<f64> = weights.iter().map(|row| row.iter().sum()).collect();
+
+    let mut scores = vec![1.0; count];
+    for _ in 0..TEXT_RANK_ITERATIONS {
+        let previous = scores.clone();
+        for i in 0..count {
+            let incoming: f64 = (0..count)
+                .filter(|&j| totals[j] > 0.0)
+                .map(|j| weights[j][i] / totals[j] * previous[j])
+                .sum();
+            scores[i] = (1.0 - DAMPING) + DAMPING * incoming;
+        }
+        let change: f64 = scores
+            .iter()
+            .zip(&previous)
+            .map(|(score, previous)| (score - previous).abs())
+            .sum();
+        if change < CONVERGENCE {
+            break;
+        }
+    }
+    scores
+}
+
+impl ExtractiveSummarizer {
+    /// Summarise without needing to await anything, returns `None` when there is no text.
+    pub fn summarise_text(&self, title: &str, text: &str) -> Option<Summary> {
+        let text = crate::search::html_to_text(text);
+        let sentences: Vec<String> = split_sentences(&text)
+            .into_iter()
+            .flat_map(bound_sentence)
+            .take(MAX_SENTENCES)
+            .collect();
+        if sentences.is_empty() {
+            return None;
+        }
+
+        // Keep the best sentences, in the order they appear in
+        let scores = text_rank(&sentences);
+        let mut ranked: Vec<usize> = (0..sentences.len()).collect();
+        ranked.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]).then(a.cmp(b)));
+        ranked.truncate(self.sentences);
+        ranked.sort_unstable();
+
+        // Summaries are shown as HTML, so the plain text needs escaping
+        let summary = ranked
+            .into_iter()
+            .map(|index| sentences[index].as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(Summary {
+            title: title.to_string(),
+            summary: html_escape::encode_text(&summary).to_string(),
+            ..Summary::default()
+        })
+    }
+}
+
+#[async_trait]
+impl Summarizer for ExtractiveSummarizer {
+    async fn summarise(
+        &self,
+        _prompt: &str,
+        title: &str,
+        text: &str,
+        _usage: &mut Vec<Usage>,
+    ) -> Result<Summary, Error> {
+        self.summarise_text(title, text)
+            .ok_or(Error::Llm("No text to summarise".to_string()))
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_objects_skip_fences_and_prose() {
+        let response = "Here it is:\n```json\n{\"title\": \"T\", \"summary\": \"S\"}\n```\nDone.";
+        assert_eq!(
+            json_objects(response).next(),
+            Some("{\"title\": \"T\", \"summary\": \"S\"}")
+        );
+    }
+
+    #[test]
+    fn json_objects_ignore_braces_in_strings() {
+        let response = r#"{"title": "a } b", "summary": "{ \" }"}"#;
+        assert_eq!(json_objects(response).next(), Some(response));
+    }
+
+    #[test]
+    fn json_objects_try_every_brace() {
+        let response = r#"Sure {see} {x {"title": "T", "summary": "S"}"#;
+        let objects: Vec<_> = json_objects(response).collect();
+        assert_eq!(objects, ["{see}", r#"{"title": "T", "summary": "S"}"#]);
+    }
+
+    #[test]
+    fn repair_json_escapes_line_breaks_in_strings() {
+        let json = "{\"summary\": \"one\r\ntwo\tthree\"}";
+        assert_eq!(repair_json(json), "{\"summary\": \"one\\ntwo\\tthree\"}");
+    }
+
+    #[test]
+    fn repair_json_drops_trailing_commas() {
+        let json = r#"{"tags": ["a", "b", ], "summary": "x, }",
+        }"#;
+        let repaired = repair_json(json);
+        assert_eq!(repaired, r#"{"tags": ["a", "b"], "summary": "x, }"}"#);
+        assert!(serde_json::from_str::<serde_json::Value>(&repaired).is_ok());
+    }
+
+    #[test]
+    fn parse_summary_reads_plain_json() {
+        let summary = parse_summary(r#"{"title": "T", "summary": "S", "tags": ["a"]}"#).unwrap();
+        assert_eq!(summary.title, "T");
+        assert_eq!(summary.summary, "S");
+        assert_eq!(summary.tags, ["a"]);
+    }
+
+    #[test]
+    fn parse_summary_accepts_capitalised_keys() {
+        let summary = parse_summary(r#"{"Title": "T", "Summary": "S"}"#).unwrap();
+        assert_eq!(summary.title, "T");
+        assert_eq!(summary.summary, "S");
+    }
+
+    #[test]
+    fn parse_summary_finds_the_object_after_other_braces() {
+        let summary = parse_summary(r#"Sure {see} {"title": "T", "summary": "S"}"#).unwrap();
+        assert_eq!(summary.title, "T");
+    }
+
+    #[test]
+    fn parse_summary_repairs_fenced_json() {
+        let response = "```json\n{\"title\": \"T\",\n\"summary\": \"line one\nline two\",\n}\n```";
+        let summary = parse_summary(response).unwrap();
+        assert_eq!(summary.summary, "line one\nline two");
+    }
+
+    #[test]
+    fn parse_summary_fails_without_an_object() {
+        assert!(parse_summary("I cannot summarise this article.").is_err());
+        assert!(parse_summary(r#"{"title": "T"}"#).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use crate::error::Error;
+use axum::response::Json;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sled::Db;
+use std::{future::Future, sync::Arc};
+use tokio::time::{sleep, Duration};
+
+/// Wait before retrying a task that failed once, doubled with every failure in a row.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+/// Longest the backoff grows to, tasks that run less often than this keep their period.
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// How the last runs of a background task went.
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+pub struct TaskStatus {
+    pub last_run: Option<String>,
+    pub last_success: Option<String>,
+    pub last_error: Option<String>,
+    /// Failures since the last success.
+    pub failures: u32,
+    pub next_run: Option<String>,
+}
+
+/// Function to retrieve a task's status from the database.
+fn get_task_status_from_db(db: &Db, name: &str) -> Option<TaskStatus> {
+    let ivec = db.get(format!("task:{name}")).ok()??;
+    serde_json::from_slice(&ivec).ok()
+}
+
+/// Function to store a task's status into the database.
+fn store_task_status_to_db(db: &Db, name: &str, status: &TaskStatus) -> Result<(), Error> {
+    db.insert(format!("task:{name}"), serde_json::to_vec(status)?)?;
+    db.flush()?;
+    Ok(())
+}
+
+/// How long to wait after a number of failures in a row.
+fn backoff(failures: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// Run a background task every `period` forever. Errors and panics are logged and recorded, and
+/// the task is retried with an exponential backoff instead of taking the server down with it. A
+/// failing task is never run more often than a healthy one.
+pub async fn supervise<F, Fut>(db: Arc<Db>, name: &'static str, period: Duration, task: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    let mut status = get_task_status_from_db(&db, name).unwrap_or_default();
+    loop {
+        // Run it on its own so a panic ends only this run
+        let result = match tokio::spawn(task()).await {
+            Ok(result) => result,
+            Err(e) => Err(Error::Storage(format!("Task panicked: {e}"))),
+        };
+
+        let now = Utc::now();
+        status.last_run = Some(now.to_rfc3339());
+        let wait = match result {
+            Ok(()) => {
+                status.last_success = status.last_run.clone();
+                status.failures = 0;
+                period
+            }
+            Err(e) => {
+                status.failures += 1;
+                let wait = backoff(status.failures).max(period);
+                eprintln!(
+                    "Error running {name}, failed {} times in a row, retrying in {}s: {e}",
+                    status.failures,
+                    wait.as_secs()
+                );
+                status.last_error = Some(e.to_string());
+                wait
+            }
+        };
+        status.next_run = chrono::Duration::from_std(wait)
+            .ok()
+            .map(|wait| (now + wait).to_rfc3339());
+        if let Err(e) = store_task_status_to_db(&db, name, &status) {
+            eprintln!("Error recording status of {name}: {e}");
+        }
+
+        sleep(wait).await;
+    }
+}
+
+/// Get the status of every background task
+#[allow(clippy::unused_async)]
+pub async fn get_tasks(db: Arc<Db>) -> Result<Json<serde_json::Value>, Error> {
+    let tasks = db
+        .scan_prefix("task:")
+        .map(|entry| {
+            let (key, value) = entry?;
+            let status: TaskStatus = serde_json::from_slice(&value)?;
+            let name = String::from_utf8_lossy(&key["task:".len()..]).to_string();
+            Ok(json!({"name": name, "status": status}))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Json(json!(tasks)))
+}
@@ -0,0 +1,105 @@
+use crate::{error::Error, extract::QueryParams};
+use axum::response::Json;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sled::Db;
+use std::sync::Arc;
+
+/// Tokens used and estimated cost of a single request to a model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+/// Running totals of the usage for one day, model and channel.
+#[derive(Deserialize, Serialize, Default, Debug)]
+struct UsageTotals {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: f64,
+}
+
+fn ledger_key(date: &str, model: &str, channel: &str) -> String {
+    format!("usage:{date}\0{model}\0{channel}")
+}
+
+/// Add requests made on behalf of a channel to today's totals in the usage ledger.
+pub fn record(db: &Db, channel: &str, usage: &[Usage]) -> Result<(), Error> {
+    let date = Utc::now().format("%Y-%m-%d").to_string();
+    for request in usage {
+        db.update_and_fetch(ledger_key(&date, &request.model, channel), |old| {
+            let mut totals: UsageTotals = old
+                .and_then(|old| serde_json::from_slice(old).ok())
+                .unwrap_or_default();
+            totals.requests += 1;
+            totals.prompt_tokens += request.prompt_tokens;
+            totals.completion_tokens += request.completion_tokens;
+            totals.cost += request.cost;
+            serde_json::to_vec(&totals).ok()
+        })?;
+    }
+    db.flush()?;
+    Ok(())
+}
+
+/// Estimated cost of everything used so far this calendar month.
+pub fn month_cost(db: &Db) -> f64 {
+    let month = Utc::now().format("%Y-%m").to_string();
+    db.scan_prefix(format!("usage:{month}"))
+        .values()
+        .filter_map(Result::ok)
+        .filter_map(|value| serde_json::from_slice::<UsageTotals>(&value).ok())
+        .map(|totals| totals.cost)
+        .sum()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatsQuery {
+    /// First day to include, as `YYYY-MM-DD`.
+    pub since: Option<String>,
+}
+
+/// Get the usage ledger of the summarisation models
+#[allow(clippy::unused_async)]
+pub async fn get_llm_stats(
+    QueryParams(query): QueryParams<StatsQuery>,
+    db: Arc<Db>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let since = query.since.unwrap_or_default();
+    let entries: Vec<_> = db
+        .range(format!("usage:{since}")..)
+        .filter_map(Result::ok)
+        .take_while(|(key, _)| key.starts_with(b"usage:"))
+        .filter_map(|(key, value)| {
+            let key = String::from_utf8_lossy(&key["usage:".len()..]).to_string();
+            let mut parts = key.splitn(3, '\0');
+            let (date, model, channel) = (parts.next()?, parts.next()?, parts.next()?);
+            let totals: UsageTotals = serde_json::from_slice(&value).ok()?;
+            Some(json!({
+                "date": date,
+                "model": model,
+                "channel": channel,
+                "requests": totals.requests,
+                "prompt_tokens": totals.prompt_tokens,
+                "completion_tokens": totals.completion_tokens,
+                "cost": totals.cost,
+            }))
+        })
+        .collect();
+
+    let budget = crate::config::load()
+        .ok()
+        .and_then(|config| config.summarizer)
+        .and_then(|summarizer| crate::summarizer::from_config(&summarizer).monthly_budget());
+
+    Ok(Json(json!({
+        "month_cost": month_cost(&db),
+        "monthly_budget": budget,
+        "usage": entries,
+    })))
+}
@@ -1,4 +1,4 @@
-use crate::gpt;
+use crate::{error::Error, gpt};
 use base64::{engine::general_purpose, Engine};
 use chrono::{Timelike, Utc};
 use image::ImageOutputFormat;
@@ -8,8 +8,14 @@ use std::{
 };
 
 #[allow(clippy::cast_possible_truncation)]
-async fn fetch_weather() -> Result<String, Box<dyn std::error::Error>> {
-    let response: serde_json::Value = reqwest::get("https://api.open-meteo.com/v1/forecast?latitude=52.6369&longitude=-1.1398&current_weather=true").await?.json().await?;
+async fn fetch_weather() -> Result<String, Error> {
+    let weather_error = |e: reqwest::Error| Error::Scrape(e.to_string());
+    let response: serde_json::Value = reqwest::get("https://api.open-meteo.com/v1/forecast?latitude=52.6369&longitude=-1.1398&current_weather=true")
+        .await
+        .map_err(weather_error)?
+        .json()
+        .await
+        .map_err(weather_error)?;
     let temperature: isize = response["current_weather"]["temperature"]
         .as_f64()
         .unwrap_or(0.0)
@@ -51,7 +57,7 @@ async fn fetch_weather() -> Result<String, Box<dyn std::error::Error>> {
     Ok(format!("{temperature_description} {weather_description}"))
 }
 
-pub async fn generate_prompt() -> Result<String, Box<dyn std::error::Error>> {
+pub async fn generate_prompt() -> Result<String, Error> {
     let date = Utc::now().format("%Y-%m-%d").to_string();
     let time = match Utc::now().hour() {
         5..=7 => "early morning",
@@ -69,7 +75,13 @@ pub async fn generate_prompt() -> Result<String, Box<dyn std::error::Error>> {
     gpt::process(input, "gpt-4", 128u16).await
 }
 
-pub async fn generate_image(prompt: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Key for the image API, checked before anything is spent on a prompt.
+pub fn image_api_key() -> Result<String, Error> {
+    std::env::var("STABLE_DIFFUSION_API_KEY")
+        .map_err(|_| Error::Llm("STABLE_DIFFUSION_API_KEY not set".to_string()))
+}
+
+pub async fn generate_image(api_key: &str, prompt: &str) -> Result<(), Error> {
     let body = serde_json::json!({
         "steps": 50,
         "width": 1344,
@@ -85,8 +97,7 @@ pub async fn generate_image(prompt: &str) -> Result<(), Box<dyn std::error::Erro
     });
 
     let client = reqwest::Client::new();
-    let api_key =
-        std::env::var("STABLE_DIFFUSION_API_KEY").expect("STABLE_DIFFUSION_API_KEY not set");
+    let image_error = |e: reqwest::Error| Error::Llm(format!("Failed to generate image: {e}"));
     let response: serde_json::Value = client
         .post("https://api.stability.ai/v1/generation/stable-diffusion-xl-1024-v1-0/text-to-image")
         .header("Accept", "application/json")
@@ -94,9 +105,11 @@ pub async fn generate_image(prompt: &str) -> Result<(), Box<dyn std::error::Erro
         .header("Authorization", format!("Bearer {api_key}"))
         .json(&body)
         .send()
-        .await?
+        .await
+        .map_err(image_error)?
         .json()
-        .await?;
+        .await
+        .map_err(image_error)?;
 
     // Ensure the wallpapers directory exists
     create_dir_all("./wallpapers")?;
@@ -105,10 +118,18 @@ pub async fn generate_image(prompt: &str) -> Result<(), Box<dyn std::error::Erro
     let datetime = chrono::DateTime::<chrono::Utc>::from(date);
     let formatted_date = datetime.format("%d.%m");
 
-    for image in response["artifacts"].as_array().unwrap() {
-        let base64_str = image["base64"].as_str().unwrap();
-        let decoded = general_purpose::STANDARD.decode(base64_str)?;
-        let img = image::load_from_memory(&decoded)?;
+    let unusable = |reason: &str| Error::Llm(format!("Unusable image response: {reason}"));
+    let artifacts = response["artifacts"]
+        .as_array()
+        .ok_or_else(|| unusable("no artifacts"))?;
+    for image in artifacts {
+        let base64_str = image["base64"]
+            .as_str()
+            .ok_or_else(|| unusable("no image data"))?;
+        let decoded = general_purpose::STANDARD
+            .decode(base64_str)
+            .map_err(|e| unusable(&e.to_string()))?;
+        let img = image::load_from_memory(&decoded).map_err(|e| unusable(&e.to_string()))?;
 
         // Save as WebP
         let sanitized_prompt = prompt
@@ -124,11 +145,13 @@ pub async fn generate_image(prompt: &str) -> Result<(), Box<dyn std::error::Erro
             .collect::<String>();
         let file_name = format!("./wallpapers/{formatted_date}-{sanitized_prompt}.webp");
         let mut output = File::create(&file_name)?;
-        img.write_to(&mut output, ImageOutputFormat::WebP)?;
+        img.write_to(&mut output, ImageOutputFormat::WebP)
+            .map_err(|e| Error::Storage(e.to_string()))?;
 
         create_dir_all("./assets")?;
         let mut bg_output = File::create("./assets/background.webp")?;
-        img.write_to(&mut bg_output, ImageOutputFormat::WebP)?;
+        img.write_to(&mut bg_output, ImageOutputFormat::WebP)
+            .map_err(|e| Error::Storage(e.to_string()))?;
     }
 
     Ok(())